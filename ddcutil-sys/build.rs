@@ -1,6 +1,8 @@
 #[cfg(feature = "bindgen")]
 pub fn main() {
-    println!("cargo:rustc-link-lib=ddcutil");
+    if cfg!(feature = "link") {
+        println!("cargo:rustc-link-lib=ddcutil");
+    }
     let out_dir = std::path::PathBuf::from(std::env::var_os("OUT_DIR").expect("OUT_DIR"));
 
     let bindings = bindgen::Builder::default()
@@ -19,6 +21,9 @@ pub fn main() {
 }
 #[cfg(not(feature = "bindgen"))]
 pub fn main() {
+    if !cfg!(feature = "link") {
+        return;
+    }
     if pkg_config::probe_library("ddcutil").is_ok() {
         println!("ddcutil found via pkg-config");
     } else {
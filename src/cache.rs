@@ -0,0 +1,147 @@
+use crate::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Where the connected monitors are listed by the kernel
+pub const SYSFS_DRM: &str = "/sys/class/drm";
+
+/// The displays found by the last full probe, so the next commands can skip probing
+///
+/// Each display is keyed by the hash of its EDID. The cache is only used while the same monitors
+/// are connected to the same drm connectors and i2c buses, which is cheap to check in sysfs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DetectionCache {
+    pub displays: Vec<CachedDisplay>,
+    /// [`edid_hash`] of the connected monitors the probe didn't find (eg. a laptop panel), they
+    /// don't invalidate the cache
+    #[serde(default)]
+    pub ignored: Vec<u64>,
+}
+
+/// A display found by a full probe
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedDisplay {
+    /// [`edid_hash`] of the EDID of the display
+    pub edid: u64,
+    pub bus: i32,
+    pub identity: DisplayIdentity,
+}
+
+/// A connected drm connector, as seen in sysfs
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Connector {
+    name: String,
+    edid: u64,
+    bus: Option<i32>,
+}
+
+/// FNV-1a hash of the 128 byte EDID base block
+///
+/// Stable across builds, unlike the hashers of the standard library.
+pub fn edid_hash(edid: &[u8]) -> u64 {
+    edid.iter()
+        .take(Edid::LEN)
+        .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+impl DetectionCache {
+    /// `$XDG_CACHE_HOME/ddcbacklight/displays.json`, falling back to `~/.cache`
+    pub fn path() -> Option<PathBuf> {
+        let dir = std::env::var_os("XDG_CACHE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+        Some(dir.join("ddcbacklight").join("displays.json"))
+    }
+
+    /// Read the cache, `None` if it doesn't exist or can't be read
+    pub fn load(path: &Path) -> Option<Self> {
+        let file = std::fs::read(path).ok()?;
+        serde_json::from_slice(&file)
+            .inspect_err(|e| tracing::warn!("Ignoring invalid cache {}: {}", path.display(), e))
+            .ok()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_vec_pretty(self).map_err(std::io::Error::from)?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// Whether the cached monitors are still connected on the same connectors and buses, and no
+    /// other monitor but the ignored ones was connected
+    ///
+    /// `sysfs` is usually [`SYSFS_DRM`].
+    pub fn is_valid(&self, sysfs: &Path) -> bool {
+        let connectors = match connectors(sysfs) {
+            Ok(connectors) => connectors,
+            Err(e) => {
+                tracing::debug!("Unable to list the drm connectors: {}", e);
+                return false;
+            }
+        };
+        let cached = connectors
+            .iter()
+            .filter(|connector| !self.ignored.contains(&connector.edid))
+            .collect::<Vec<_>>();
+        cached.len() == self.displays.len()
+            && self.displays.iter().all(|display| {
+                cached.iter().any(|connector| {
+                    connector.name == display.identity.drm
+                        && connector.edid == display.edid
+                        && connector.bus.is_none_or(|bus| bus == display.bus)
+                })
+            })
+    }
+
+    /// Ignore the monitors connected in `sysfs` that aren't cached, the probe didn't find them
+    pub fn ignore_uncached(&mut self, sysfs: &Path) {
+        let Ok(connectors) = connectors(sysfs) else {
+            return;
+        };
+        self.ignored = connectors
+            .into_iter()
+            .map(|connector| connector.edid)
+            .filter(|edid| self.displays.iter().all(|display| display.edid != *edid))
+            .collect();
+    }
+}
+
+/// The connected drm connectors that have an EDID
+fn connectors(sysfs: &Path) -> std::io::Result<Vec<Connector>> {
+    let mut connectors = Vec::new();
+    for entry in std::fs::read_dir(sysfs)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        // Connectors are named `card1-DP-1`, skip the cards themselves
+        if !name.starts_with("card") || !name.contains('-') {
+            continue;
+        }
+        let connected = std::fs::read_to_string(path.join("status"))
+            .is_ok_and(|status| status.trim() == "connected");
+        let edid = std::fs::read(path.join("edid")).unwrap_or_default();
+        if !connected || edid.len() < Edid::LEN {
+            continue;
+        }
+        let bus = std::fs::read_link(path.join("ddc")).ok().and_then(|link| {
+            link.file_name()?
+                .to_str()?
+                .strip_prefix("i2c-")?
+                .parse()
+                .ok()
+        });
+        connectors.push(Connector {
+            name: name.to_string(),
+            edid: edid_hash(&edid),
+            bus,
+        });
+    }
+    Ok(connectors)
+}
@@ -0,0 +1,98 @@
+mod parser;
+pub use parser::{CapabilityString, Segment};
+
+use crate::error::*;
+use serde::Serialize;
+
+/// The parsed MCCS capabilities of a monitor
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Capabilities {
+    pub model: Option<String>,
+    /// MCCS version (eg. `2.1`)
+    pub mccs_version: Option<String>,
+    /// Supported DDC/CI commands
+    pub commands: Vec<u8>,
+    /// Supported VCP features
+    pub features: Vec<VcpCapability>,
+}
+
+/// A VCP feature listed in the capabilities string
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct VcpCapability {
+    pub code: u8,
+    /// The allowed values of a non-continuous feature (eg. the inputs for `0x60`)
+    pub values: Vec<u8>,
+}
+
+impl Capabilities {
+    /// Parse a capabilities string, see [`CapabilityString::parse`]
+    pub fn parse(raw: &str) -> Result<Self> {
+        CapabilityString::parse(raw).map(Self::from)
+    }
+
+    pub fn feature(&self, code: u8) -> Option<&VcpCapability> {
+        self.features.iter().find(|f| f.code == code)
+    }
+
+    pub fn supports(&self, code: u8) -> bool {
+        self.feature(code).is_some()
+    }
+
+    /// Fail with [`DdcutilErrorKind::Unsupported`] if `capabilities` lack `vcp`
+    ///
+    /// Monitors whose capabilities can't be read or parsed are assumed to support it, many
+    /// report broken ones.
+    pub(crate) fn require(capabilities: Result<Self>, vcp: u8) -> Result<()> {
+        match capabilities {
+            Ok(capabilities) if !capabilities.supports(vcp) => {
+                tracing::error!("The monitor doesn't support VCP {:#04x}", vcp);
+                Err(DDCError::new(DdcutilErrorKind::Unsupported))
+            }
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::debug!("Unable to check the capabilities of the monitor: {:?}", e);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl From<CapabilityString> for Capabilities {
+    /// Repeated segments are merged, the first `model` and `mccs_ver` win
+    fn from(parsed: CapabilityString) -> Self {
+        let mut capabilities = Self::default();
+        for segment in parsed.segments {
+            match segment {
+                Segment::Model(model) => {
+                    capabilities.model.get_or_insert(model);
+                }
+                Segment::MccsVersion(version) => {
+                    capabilities.mccs_version.get_or_insert(version);
+                }
+                Segment::Commands(commands) => capabilities.commands.extend(commands),
+                Segment::Vcp(features) => {
+                    for feature in features {
+                        match capabilities
+                            .features
+                            .iter_mut()
+                            .find(|f| f.code == feature.code)
+                        {
+                            Some(existing) => existing.values.extend(feature.values),
+                            None => capabilities.features.push(feature),
+                        }
+                    }
+                }
+                Segment::Protocol(_) | Segment::Type(_) | Segment::Unknown { .. } => (),
+            }
+        }
+        capabilities
+    }
+}
+
+impl core::str::FromStr for Capabilities {
+    type Err = crate::DDCError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
@@ -0,0 +1,213 @@
+//! A parser for MCCS capabilities strings that doesn't depend on libddcutil
+//!
+//! Monitors are not very strict about the format, so the parser tries to make sense of the common
+//! vendor quirks instead of rejecting the whole string:
+//! - missing or stray parentheses and trailing `NUL` bytes
+//! - upper case or mixed case segment names
+//! - hex bytes without separators (`vcp(021012)`), `0x` prefixes and lower case digits
+//! - whitespace between a vcp code and its values (`60 (0F 11)`)
+//! - the same segment repeated (some monitors split `vcp` in two)
+//! - junk between segments
+use super::VcpCapability;
+use crate::{DDCError, DdcutilErrorKind, Result};
+use core::fmt;
+
+/// The parsed tree of a capabilities string, the segments are kept in the order they were reported
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CapabilityString {
+    pub segments: Vec<Segment>,
+}
+
+/// A top level `name(...)` entry of a capabilities string
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    /// `prot(monitor)`
+    Protocol(String),
+    /// `type(lcd)`
+    Type(String),
+    /// `model(U2720Q)`
+    Model(String),
+    /// `mccs_ver(2.1)`
+    MccsVersion(String),
+    /// `cmds(01 02 03)`
+    Commands(Vec<u8>),
+    /// `vcp(10 12 60(0F 11))`
+    Vcp(Vec<VcpCapability>),
+    /// Any other segment (eg. `mswhql`, `asset_eep`, `vcpname`) with its unparsed contents
+    Unknown { name: String, value: String },
+}
+
+impl CapabilityString {
+    /// Parse a capabilities string
+    ///
+    /// Only fails if nothing that looks like a segment was found.
+    pub fn parse(raw: &str) -> Result<Self> {
+        let raw = raw.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+        // The outer parentheses are optional and often unbalanced, stray closing parentheses are
+        // skipped along with the rest of the junk between the segments
+        let mut rest = raw.strip_prefix('(').unwrap_or(raw);
+        let mut segments = Vec::new();
+        while !rest.is_empty() {
+            let name_len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            if name_len == 0 {
+                // Skip junk between the segments, one character at a time
+                let skip = rest.chars().next().map_or(1, char::len_utf8);
+                rest = &rest[skip..];
+                continue;
+            }
+            let name = &rest[..name_len];
+            let after = rest[name_len..].trim_start();
+            match after.strip_prefix('(') {
+                Some(inner) => {
+                    let (value, remaining) = balanced(inner);
+                    segments.push(Segment::new(name, value));
+                    rest = remaining;
+                }
+                // A bare word, eg. the `bin` of `edid bin(...)`
+                None => rest = after,
+            }
+        }
+        if segments.is_empty() {
+            tracing::debug!("No segments in capabilities string {:?}", raw);
+            return Err(DDCError::new(DdcutilErrorKind::MalformedCapabilities));
+        }
+        Ok(Self { segments })
+    }
+}
+
+/// Split `s` at the parenthesis closing the one just before it
+///
+/// Returns the contents and the rest of the string after the closing parenthesis, a missing
+/// closing parenthesis takes everything until the end of the string.
+fn balanced(s: &str) -> (&str, &str) {
+    let mut depth = 0usize;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return (&s[..i], &s[i + 1..]),
+            ')' => depth -= 1,
+            _ => (),
+        }
+    }
+    (s, "")
+}
+
+/// All the hex bytes in `s`, ignoring any nesting
+fn hex_bytes(s: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut rest = s;
+    while let Some((run, remaining)) = hex_run(rest) {
+        bytes.extend(run);
+        rest = remaining;
+    }
+    bytes
+}
+
+/// The next run of hex digits in `s` split into bytes, and the rest of the string after it
+fn hex_run(s: &str) -> Option<(Vec<u8>, &str)> {
+    let start = s.find(|c: char| c.is_ascii_hexdigit())?;
+    let mut s = &s[start..];
+    if let Some(rest) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        if rest.starts_with(|c: char| c.is_ascii_hexdigit()) {
+            s = rest;
+        }
+    }
+    let end = s.find(|c: char| !c.is_ascii_hexdigit()).unwrap_or(s.len());
+    let bytes = s.as_bytes()[..end]
+        .chunks(2)
+        .filter_map(|pair| u8::from_str_radix(core::str::from_utf8(pair).ok()?, 16).ok())
+        .collect();
+    Some((bytes, &s[end..]))
+}
+
+/// The features of a `vcp(...)` segment, values in parentheses belong to the code before them
+fn vcp(s: &str) -> Vec<VcpCapability> {
+    let mut features: Vec<VcpCapability> = Vec::new();
+    let mut rest = s;
+    while let Some(next) = rest.find(|c: char| c == '(' || c.is_ascii_hexdigit()) {
+        rest = &rest[next..];
+        if let Some(inner) = rest.strip_prefix('(') {
+            let (values, remaining) = balanced(inner);
+            match features.last_mut() {
+                Some(feature) => feature.values.extend(hex_bytes(values)),
+                None => tracing::debug!("Values without a vcp code: {:?}", values),
+            }
+            rest = remaining;
+        } else if let Some((codes, remaining)) = hex_run(rest) {
+            features.extend(codes.into_iter().map(|code| VcpCapability {
+                code,
+                values: Vec::new(),
+            }));
+            rest = remaining;
+        }
+    }
+    features
+}
+
+impl Segment {
+    fn new(name: &str, value: &str) -> Self {
+        let text = || value.trim().to_string();
+        match name.to_ascii_lowercase().as_str() {
+            "prot" => Self::Protocol(text()),
+            "type" => Self::Type(text()),
+            "model" => Self::Model(text()),
+            "mccs_ver" => Self::MccsVersion(text()),
+            "cmds" => Self::Commands(hex_bytes(value)),
+            "vcp" => Self::Vcp(vcp(value)),
+            _ => Self::Unknown {
+                name: name.to_string(),
+                value: value.to_string(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex = |bytes: &[u8]| {
+            bytes
+                .iter()
+                .map(|b| format!("{:02X}", b))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        match self {
+            Self::Protocol(value) => write!(f, "prot({})", value),
+            Self::Type(value) => write!(f, "type({})", value),
+            Self::Model(value) => write!(f, "model({})", value),
+            Self::MccsVersion(value) => write!(f, "mccs_ver({})", value),
+            Self::Commands(commands) => write!(f, "cmds({})", hex(commands)),
+            Self::Vcp(features) => {
+                let features = features
+                    .iter()
+                    .map(|feature| match feature.values.is_empty() {
+                        true => format!("{:02X}", feature.code),
+                        false => format!("{:02X}({})", feature.code, hex(&feature.values)),
+                    });
+                write!(f, "vcp({})", features.collect::<Vec<_>>().join(" "))
+            }
+            Self::Unknown { name, value } => write!(f, "{}({})", name, value),
+        }
+    }
+}
+
+/// Prints the canonical form of the capabilities string
+impl fmt::Display for CapabilityString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(")?;
+        for segment in &self.segments {
+            write!(f, "{}", segment)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl core::str::FromStr for CapabilityString {
+    type Err = DDCError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
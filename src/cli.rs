@@ -1,28 +1,236 @@
+use crate::*;
 use clap::*;
+use std::ffi::OsString;
+use std::io::Write;
 
-#[derive(Debug, Parser)]
+#[derive(Debug, Clone, Parser)]
+#[command(name = "xbacklight")]
 pub struct Args {
     #[clap(subcommand)]
     pub op: Op,
     #[arg(short, long, action = clap::ArgAction::Count, global = true)]
     pub verbosity: u8,
+    #[arg(short, long, value_enum, default_value_t, global = true)]
+    pub output: OutputFormat,
+    /// Unit the brightness values are printed in
+    #[arg(long, value_enum, default_value_t, global = true)]
+    pub unit: BrightnessUnit,
+    /// Probe the displays instead of using the ones found by the last probe
+    #[arg(long, global = true)]
+    pub no_cache: bool,
+    /// Run the command here even if a daemon is running
+    #[arg(long, global = true)]
+    pub no_daemon: bool,
+    /// Where the values set are remembered for `watch --restore`, nothing is remembered if unset
+    #[arg(skip)]
+    pub state: Option<std::path::PathBuf>,
+    /// Where the profiles are kept, [`Profile::dir`] if unset
+    #[arg(skip)]
+    pub profiles: Option<std::path::PathBuf>,
+    /// The monitor aliases and defaults
+    #[arg(skip)]
+    pub config: Config,
 }
 
-#[derive(Debug, clap::Args)]
+impl Args {
+    /// Parse `args`, accepting the classic xbacklight syntax (`-set 50`, `-inc 10 -time 100`,
+    /// `= 50`, ...) as well when invoked as `xbacklight`
+    pub fn parse_invocation<I, T>(args: I) -> core::result::Result<Self, Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString>,
+    {
+        Self::try_parse_from(Self::translate(args)?)
+    }
+
+    /// The regular arguments for `args`, see [`Args::parse_invocation`]
+    pub fn translate<I, T>(args: I) -> core::result::Result<Vec<OsString>, Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString>,
+    {
+        let args = args.into_iter().map(Into::into).collect::<Vec<OsString>>();
+        let invoked_as_xbacklight = args
+            .first()
+            .and_then(|arg0| std::path::Path::new(arg0).file_name())
+            .is_some_and(|name| name == "xbacklight");
+        // A bare `xbacklight` prints the brightness
+        let classic = args.len() == 1 || args.iter().skip(1).any(is_compat);
+        if invoked_as_xbacklight && classic {
+            compat(&args)
+        } else {
+            Ok(args)
+        }
+    }
+}
+
+/// The classic xbacklight options, along with the `=`, `+` and `-` shorthands for set, inc and dec
+const COMPAT_OPTIONS: &[&str] = &[
+    "-set", "-inc", "-dec", "-get", "-time", "-steps", "-display", "=", "+", "-",
+];
+
+fn is_compat(arg: &OsString) -> bool {
+    arg.to_str()
+        .is_some_and(|arg| COMPAT_OPTIONS.contains(&arg))
+}
+
+/// Translate classic xbacklight arguments into the regular ones
+///
+/// Like xbacklight, no operation means `-get`, `-get` only prints the values and changes fade
+/// over 200ms in 20 steps unless told otherwise. X displays (`-display :0`) are ignored, any other
+/// `-display` selects the monitors by name. Anything that isn't an xbacklight option is passed on
+/// as is, so `-v` and `-o json` still work.
+fn compat(args: &[OsString]) -> core::result::Result<Vec<OsString>, Error> {
+    let mut op = None;
+    let (mut time, mut steps) = (OsString::from("200"), OsString::from("20"));
+    let mut names = Vec::new();
+    let mut rest = Vec::new();
+    let mut args = args.iter().cloned();
+    let program = args.next().unwrap_or_default();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next().ok_or_else(|| {
+                Error::raw(
+                    clap::error::ErrorKind::InvalidValue,
+                    format!("a value is required for '{}'\n", arg.to_string_lossy()),
+                )
+            })
+        };
+        match arg.to_str().unwrap_or_default() {
+            "-set" | "=" => op = Some(("set", Some(percent(value()?)))),
+            "-inc" | "+" => op = Some(("inc", Some(percent(value()?)))),
+            "-dec" | "-" => op = Some(("dec", Some(percent(value()?)))),
+            "-get" => op = Some(("get", None)),
+            "-time" => time = value()?,
+            "-steps" => steps = value()?,
+            "-display" => {
+                let display = value()?;
+                if !display.to_string_lossy().starts_with(':') {
+                    names.push(display);
+                }
+            }
+            _ => rest.push(arg),
+        }
+    }
+    let (op, amount) = op.unwrap_or(("get", None));
+    let mut out = vec![program, op.into()];
+    out.extend(amount);
+    if op == "get" {
+        if !rest.iter().any(|arg| arg == "-o" || arg == "--output") {
+            out.extend(["--output".into(), "value".into()]);
+        }
+    } else {
+        out.extend(["--time".into(), time, "--steps".into(), steps]);
+    }
+    for name in names {
+        out.extend(["--names".into(), name]);
+    }
+    out.extend(rest);
+    Ok(out)
+}
+
+/// xbacklight takes fractional percentages, round them to the nearest one
+fn percent(value: OsString) -> OsString {
+    match value.to_str().map(str::parse::<f64>) {
+        Some(Ok(number)) if number >= 0.0 => format!("{}%", number.round()).into(),
+        _ => value,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, clap::Args)]
 pub struct MonitorIdentifier {
     #[arg(short, long, group = "identifier")]
     pub buses: Vec<u8>,
     #[arg(short, long, group = "identifier")]
     pub names: Vec<String>,
+    /// Monitors by their alias in the config
+    #[arg(short, long = "monitor", group = "identifier")]
+    pub monitors: Vec<String>,
+    /// Monitors matching a selector (eg. `model=DELL U2720Q`, `mfg=DEL,!serial=5KC2R83`,
+    /// `bus=4|hiddev=1`)
+    #[arg(short, long, group = "identifier", value_parser = parse_selector)]
+    pub select: Vec<DisplaySelector>,
+    /// Leave out the monitors matching a selector
+    #[arg(short = 'x', long, value_parser = parse_selector)]
+    pub exclude: Vec<DisplaySelector>,
 }
 
-#[derive(Debug, Subcommand)]
+impl MonitorIdentifier {
+    /// The displays to run the command on, fails if an alias isn't in `config`
+    pub fn selector(&self, config: &Config) -> Result<DisplaySelector> {
+        let selected = if !self.monitors.is_empty() {
+            let monitors = self
+                .monitors
+                .iter()
+                .map(|alias| match config.monitors.get(alias) {
+                    Some(monitor) => Ok(monitor.selector()),
+                    None => {
+                        tracing::error!("There is no monitor {:?} in the config", alias);
+                        Err(DDCError::new(DdcutilErrorKind::Other))
+                    }
+                });
+            DisplaySelector::any(monitors.collect::<Result<_>>()?)
+        } else if !self.names.is_empty() {
+            let names = self.names.iter().cloned().map(DisplaySelector::Connector);
+            DisplaySelector::any(names.collect())
+        } else if !self.buses.is_empty() {
+            let buses = self
+                .buses
+                .iter()
+                .map(|bus| DisplaySelector::Bus(*bus as i32));
+            DisplaySelector::any(buses.collect())
+        } else if !self.select.is_empty() {
+            DisplaySelector::any(self.select.clone())
+        } else {
+            DisplaySelector::default()
+        };
+        if self.exclude.is_empty() {
+            return Ok(selected);
+        }
+        let excluded = DisplaySelector::any(self.exclude.clone());
+        Ok(DisplaySelector::all(vec![
+            selected,
+            DisplaySelector::Not(Box::new(excluded)),
+        ]))
+    }
+}
+
+/// How `set`, `inc` and `dec` get to the new brightness
+#[derive(Debug, Clone, PartialEq, Eq, clap::Args)]
+pub struct FadeArgs {
+    /// Fade to the new brightness over this many milliseconds
+    #[arg(long, default_value_t = 0)]
+    pub time: u64,
+    /// Number of steps of the fade
+    #[arg(long, default_value_t = 20)]
+    pub steps: u32,
+    #[arg(long, value_enum, default_value_t)]
+    pub easing: Easing,
+}
+
+impl From<&FadeArgs> for Fade {
+    fn from(args: &FadeArgs) -> Self {
+        Fade::new(core::time::Duration::from_millis(args.time), args.steps).with_easing(args.easing)
+    }
+}
+
+#[derive(Debug, Clone, Subcommand)]
 pub enum Op {
+    /// List the detected displays and their identity
+    #[clap(name = "detect", visible_alias = "list")]
+    Detect {
+        #[clap(flatten)]
+        monitor: MonitorIdentifier,
+    },
     #[clap(name = "set")]
     SetBrightness {
-        brightness: u8,
+        /// New brightness (`50%`, raw `30`), a change (`+10%`, `-5`) or `max` / `min`
+        #[arg(value_parser = parse_brightness::<BrightnessChange>, allow_hyphen_values = true)]
+        brightness: BrightnessChange,
         #[clap(flatten)]
         monitor: MonitorIdentifier,
+        #[clap(flatten)]
+        fade: FadeArgs,
     },
     #[clap(name = "get")]
     GetBrightness {
@@ -31,54 +239,746 @@ pub enum Op {
     },
     #[clap(name = "inc")]
     IncreaseBrightness {
-        #[arg(default_value = "10")]
-        amount: u8,
+        /// The step of the monitor in the config if not given, otherwise 10%
+        #[arg(value_parser = parse_brightness::<BrightnessAmount>)]
+        amount: Option<BrightnessAmount>,
         #[clap(flatten)]
         monitor: MonitorIdentifier,
+        #[clap(flatten)]
+        fade: FadeArgs,
     },
     #[clap(name = "dec")]
     DecreaseBrightness {
-        #[arg(default_value = "10")]
-        amount: u8,
+        /// The step of the monitor in the config if not given, otherwise 10%
+        #[arg(value_parser = parse_brightness::<BrightnessAmount>)]
+        amount: Option<BrightnessAmount>,
         #[clap(flatten)]
         monitor: MonitorIdentifier,
+        #[clap(flatten)]
+        fade: FadeArgs,
     },
     SetInput {
         #[clap(flatten)]
         monitor: MonitorIdentifier,
-        input: crate::ddc::Input,
+        /// Input source name or raw VCP 0x60 value (eg. `0x1b`), the input of the monitor in the
+        /// config if not given
+        #[arg(value_parser = InputParser)]
+        input: Option<crate::Input>,
     },
     GetInput {
         #[clap(flatten)]
         monitor: MonitorIdentifier,
     },
+    /// Show the MCCS capabilities reported by the monitor
+    Capabilities {
+        #[clap(flatten)]
+        monitor: MonitorIdentifier,
+        /// Print the unparsed capabilities string
+        #[arg(short, long)]
+        raw: bool,
+    },
+    /// Read or change the contrast
+    Contrast {
+        #[command(subcommand)]
+        op: LevelOp,
+    },
+    /// Read or change the speaker volume
+    Volume {
+        #[command(subcommand)]
+        op: LevelOp,
+    },
+    /// Mute or unmute the speakers
+    Mute {
+        #[arg(value_enum)]
+        state: MuteState,
+        #[clap(flatten)]
+        monitor: MonitorIdentifier,
+    },
+    /// Turn the monitors on, to standby, suspend or off, or print their power mode
+    Power {
+        #[arg(value_enum)]
+        op: PowerOp,
+        #[clap(flatten)]
+        monitor: MonitorIdentifier,
+    },
+    /// Save the settings of the monitors to a named profile, or apply one
+    Profile {
+        #[command(subcommand)]
+        op: ProfileOp,
+    },
+    /// Print displays being connected, disconnected, put to sleep or woken up until interrupted
+    Watch {
+        /// Set the brightness, contrast and input last set on a monitor again when it's
+        /// connected or wakes up
+        #[arg(long)]
+        restore: bool,
+    },
+    /// Keep the displays open and serve the other commands over a unix socket
+    Daemon {
+        /// Listen on this socket instead of `$XDG_RUNTIME_DIR/ddcbacklight/daemon.sock`
+        #[arg(long)]
+        socket: Option<std::path::PathBuf>,
+    },
+    /// Publish the displays on the session bus
+    #[cfg(feature = "dbus")]
+    Dbus {
+        /// Connect to the bus at this address instead of the session bus
+        #[arg(long)]
+        address: Option<String>,
+    },
     Completions {
         shell: clap_complete::Shell,
     },
 }
 
-impl ValueEnum for crate::ddc::Input {
+/// Reading or changing a feature ranging from 0 to a max like the brightness
+#[derive(Debug, Clone, Subcommand)]
+pub enum LevelOp {
+    Get {
+        #[clap(flatten)]
+        monitor: MonitorIdentifier,
+    },
+    Set {
+        /// New value (`50%`, raw `30`), a change (`+10%`, `-5`) or `max` / `min`
+        #[arg(value_parser = parse_brightness::<BrightnessChange>, allow_hyphen_values = true)]
+        value: BrightnessChange,
+        #[clap(flatten)]
+        monitor: MonitorIdentifier,
+    },
+    Inc {
+        #[arg(default_value = "10%", value_parser = parse_brightness::<BrightnessAmount>)]
+        amount: BrightnessAmount,
+        #[clap(flatten)]
+        monitor: MonitorIdentifier,
+    },
+    Dec {
+        #[arg(default_value = "10%", value_parser = parse_brightness::<BrightnessAmount>)]
+        amount: BrightnessAmount,
+        #[clap(flatten)]
+        monitor: MonitorIdentifier,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum MuteState {
+    On,
+    Off,
+    Toggle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PowerOp {
+    Get,
+    On,
+    Standby,
+    Suspend,
+    Off,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum ProfileOp {
+    /// Save the features of the monitors as the profile `name`, replacing what was saved for them
+    Save {
+        name: String,
+        #[clap(flatten)]
+        monitor: MonitorIdentifier,
+        /// The features to save, every one the monitors support by default
+        #[arg(long, value_enum, value_delimiter = ',')]
+        features: Vec<ProfileFeature>,
+    },
+    /// Set the features saved in the profile `name` on the monitors
+    Apply {
+        name: String,
+        #[clap(flatten)]
+        monitor: MonitorIdentifier,
+    },
+}
+
+impl Op {
+    /// Whether the command writes the brightness
+    pub fn changes_brightness(&self) -> bool {
+        matches!(
+            self,
+            Self::SetBrightness { .. }
+                | Self::IncreaseBrightness { .. }
+                | Self::DecreaseBrightness { .. }
+        )
+    }
+
+    /// Whether the command sets values remembered for `watch --restore`
+    pub fn is_remembered(&self) -> bool {
+        self.changes_brightness()
+            || matches!(
+                self,
+                Self::SetInput { .. }
+                    | Self::Contrast {
+                        op: LevelOp::Set { .. } | LevelOp::Inc { .. } | LevelOp::Dec { .. }
+                    }
+                    | Self::Profile {
+                        op: ProfileOp::Apply { .. }
+                    }
+            )
+    }
+
+    /// Whether the command has to run in this process rather than in the daemon
+    pub fn is_local(&self) -> bool {
+        match self {
+            Self::Watch { .. } | Self::Daemon { .. } | Self::Completions { .. } => true,
+            #[cfg(feature = "dbus")]
+            Self::Dbus { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+impl ValueEnum for crate::Input {
     fn value_variants<'a>() -> &'a [Self] {
-        &[Self::HDMI(1), Self::HDMI(2), Self::DP(1), Self::DP(2)]
+        Self::ALL
     }
 
     fn to_possible_value(&self) -> Option<builder::PossibleValue> {
-        Some(match self {
-            Self::HDMI(1) => builder::PossibleValue::new("HDMI-1"),
-            Self::HDMI(2) => builder::PossibleValue::new("HDMI-2"),
-            Self::DP(1) => builder::PossibleValue::new("DP-1"),
-            Self::DP(2) => builder::PossibleValue::new("DP-2"),
-            _ => return None,
-        })
+        self.name().map(builder::PossibleValue::new)
     }
 }
 
-pub(crate) fn completions(shell: clap_complete::Shell) {
+/// Accepts every named [`Input`] as well as raw values
+#[derive(Debug, Clone, Copy)]
+struct InputParser;
+
+impl builder::TypedValueParser for InputParser {
+    type Value = crate::Input;
+
+    fn parse_ref(
+        &self,
+        cmd: &Command,
+        arg: Option<&Arg>,
+        value: &std::ffi::OsStr,
+    ) -> core::result::Result<Self::Value, Error> {
+        match value.to_str().map(str::parse) {
+            Some(Ok(input)) => Ok(input),
+            _ => builder::EnumValueParser::<crate::Input>::new().parse_ref(cmd, arg, value),
+        }
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = builder::PossibleValue> + '_>> {
+        Some(Box::new(
+            crate::Input::ALL
+                .iter()
+                .filter_map(ValueEnum::to_possible_value),
+        ))
+    }
+}
+
+/// Parse a [`DisplaySelector`], with an error message clap can print
+fn parse_selector(value: &str) -> core::result::Result<DisplaySelector, String> {
+    value.parse().map_err(|_| {
+        format!(
+            "'{}' isn't a display selector, expected `key=value` terms joined by `,` or `|` with \
+             keys among model, serial, mfg, product, display, bus, hiddev, connector and edid",
+            value
+        )
+    })
+}
+
+/// Parse a brightness expression, with an error message clap can print
+fn parse_brightness<T: core::str::FromStr>(value: &str) -> core::result::Result<T, String> {
+    value.parse().map_err(|_| {
+        format!(
+            "'{}' isn't a brightness, expected eg. `50`, `50%`, `+10%`, `-5`, `max` or `min`",
+            value
+        )
+    })
+}
+
+pub fn completions(shell: clap_complete::Shell, out: &mut impl Write) {
     let mut command = Args::command();
-    clap_complete::aot::generate(
-        shell,
-        &mut command,
-        env!("CARGO_BIN_NAME"),
-        &mut std::io::stdout(),
-    );
+    let name = command.get_name().to_string();
+    clap_complete::aot::generate(shell, &mut command, name, out);
+}
+
+/// Run the command in the daemon if one is running, `None` if it has to run in this process
+///
+/// `argv` are the arguments `args` were parsed from, including the program name.
+pub fn forward(args: &Args, argv: &[OsString], out: &mut impl Write) -> Option<Result<()>> {
+    if args.no_daemon || args.op.is_local() {
+        return None;
+    }
+    let argv = argv
+        .iter()
+        .skip(1)
+        .map(|arg| arg.to_str().map(str::to_string))
+        .collect::<Option<Vec<_>>>()?;
+    let color = colored::control::SHOULD_COLORIZE.should_colorize();
+    let output = Daemon::new(Daemon::socket()).request(&argv, color)?;
+    Some(output.and_then(|output| Ok(out.write_all(output.as_bytes())?)))
+}
+
+/// Probe the driver and return every display matched by `monitor`
+pub(crate) fn displays<D: DDCDriverTrait>(
+    driver: &D,
+    monitor: &MonitorIdentifier,
+    config: &Config,
+) -> Result<Vec<(DisplayIdent, DisplayIdentity)>> {
+    let displays = driver.select(&monitor.selector(config)?)?;
+    for (_, identity) in &displays {
+        tracing::info!("Found display: {} ({})", identity.model, identity.io_path);
+    }
+    Ok(displays)
+}
+
+/// Apply the `change` of each display, fading to the new brightness within its configured limits
+///
+/// Claims the displays first, so fades still running in older commands stop.
+fn change_brightness<D: DDCDriverTrait>(
+    driver: &D,
+    fade: &FadeArgs,
+    unit: BrightnessUnit,
+    config: &Config,
+    displays: Vec<(DisplayIdent, DisplayIdentity)>,
+    change: &dyn Fn(&DisplayIdentity) -> BrightnessChange,
+    cancelled: &dyn Fn(&DisplayIdent) -> bool,
+) -> Result<Vec<Record>> {
+    let claims = Claims::runtime();
+    let mut targets = Vec::new();
+    for (display, identity) in &displays {
+        let backlight = driver.backlight_get(display)?;
+        let to = change(identity).apply(&backlight)?;
+        targets.push(FadeTarget {
+            display: display.clone(),
+            from: backlight.current,
+            to: config.clamp(identity, &backlight, to),
+        });
+    }
+    for (display, _) in &displays {
+        let io_path = display.io_path;
+        if let Err(e) = claims.claim(&io_path) {
+            tracing::warn!("Unable to claim {}: {:?}", io_path, e);
+        }
+    }
+    Fade::from(fade).run(driver, &targets, |display| {
+        cancelled(display) || !claims.is_claimed(&display.io_path)
+    })?;
+    displays
+        .iter()
+        .map(|(display, identity)| {
+            let backlight = driver.backlight_get(display)?;
+            Ok(Record::brightness(identity, &backlight, unit))
+        })
+        .collect()
+}
+
+/// Read or change a feature ranging from 0 to a max through `get` and `set`, like the contrast
+///
+/// Every new value is checked before changing any monitor.
+fn change_level<D: DDCDriverTrait>(
+    driver: &D,
+    op: LevelOp,
+    unit: BrightnessUnit,
+    config: &Config,
+    get: impl Fn(&DisplayIdent) -> Result<Backlight>,
+    set: impl Fn(&DisplayIdent, u16) -> Result<()>,
+    record: fn(&DisplayIdentity, &Backlight, BrightnessUnit) -> Record,
+) -> Result<Vec<Record>> {
+    let (monitor, change) = match op {
+        LevelOp::Get { monitor } => (monitor, None),
+        LevelOp::Set { value, monitor } => (monitor, Some(value)),
+        LevelOp::Inc { amount, monitor } => (monitor, Some(BrightnessChange::Increase(amount))),
+        LevelOp::Dec { amount, monitor } => (monitor, Some(BrightnessChange::Decrease(amount))),
+    };
+    let targets = displays(driver, &monitor, config)?;
+    if let Some(change) = change {
+        let mut values = Vec::new();
+        for (display, _) in &targets {
+            values.push(change.apply(&get(display)?)?);
+        }
+        for ((display, _), value) in targets.iter().zip(values) {
+            set(display, value)?;
+        }
+    }
+    targets
+        .iter()
+        .map(|(display, identity)| Ok(record(identity, &get(display)?, unit)))
+        .collect()
+}
+
+/// Read `features` from every display into the profile at `path`
+///
+/// Features a monitor doesn't support are left out, the ones that fail are reported.
+fn save_profile<D: DDCDriverTrait>(
+    driver: &D,
+    path: &std::path::Path,
+    displays: Vec<(DisplayIdent, DisplayIdentity)>,
+    features: &[ProfileFeature],
+) -> Result<Vec<Record>> {
+    let mut profile = if path.exists() {
+        Profile::load(path)?
+    } else {
+        Profile::default()
+    };
+    let features = if features.is_empty() {
+        ProfileFeature::ALL
+    } else {
+        features
+    };
+    let mut records = Vec::new();
+    for (display, identity) in displays {
+        profile.displays.remove(&Profile::key(&identity));
+        for &feature in features {
+            match driver.get_vcp(&display, feature.code()) {
+                Ok(value) => {
+                    let value = feature.value(&value);
+                    profile.set(&identity, feature, value);
+                    records.push(Record::profile(&identity, feature, value));
+                }
+                Err(e) if e.is_unsupported() => {
+                    tracing::info!("{} doesn't support {}", identity.model, feature);
+                }
+                Err(e) => records.push(Record::profile_failure(&identity, feature, &e)),
+            }
+        }
+    }
+    profile.save(path)?;
+    Ok(records)
+}
+
+/// Set the features saved in the profile at `path` on every display saved in it
+fn apply_profile<D: DDCDriverTrait>(
+    driver: &D,
+    path: &std::path::Path,
+    displays: Vec<(DisplayIdent, DisplayIdentity)>,
+) -> Result<Vec<Record>> {
+    let profile = Profile::load(path)?;
+    let mut records = Vec::new();
+    for (display, identity) in displays {
+        let Some(values) = profile.values(&identity) else {
+            tracing::warn!(
+                "Nothing is saved for {} in {}",
+                identity.model,
+                path.display()
+            );
+            continue;
+        };
+        for (&feature, &value) in values {
+            match driver.set_vcp(&display, feature.code(), value) {
+                Ok(()) => records.push(Record::profile(&identity, feature, value)),
+                Err(e) => records.push(Record::profile_failure(&identity, feature, &e)),
+            }
+        }
+    }
+    Ok(records)
+}
+
+/// Remember the values set by a command for `watch --restore`
+fn remember(state: &std::path::Path, records: &[Record]) {
+    let values = records
+        .iter()
+        .filter_map(|record| match record.kind {
+            RecordKind::Brightness => {
+                Some((&record.display, VCPFeature::BACKLIGHT, record.current?))
+            }
+            RecordKind::Contrast => Some((&record.display, VCPFeature::CONTRAST, record.current?)),
+            RecordKind::SetInput => {
+                let input = u8::try_from(record.input?).ok()?;
+                Some((&record.display, VCPFeature::INPUT, input.into()))
+            }
+            RecordKind::Profile if record.error.is_none() => {
+                Some((&record.display, record.feature?.code(), record.current?))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if !values.is_empty() {
+        RestoreState::remember(state, &values);
+    }
+}
+
+/// Reapply the remembered values to a display that was connected, woke up or stayed connected
+/// while the system was suspended
+fn restore<D: DDCDriverTrait>(driver: &D, state: &std::path::Path, event: &DisplayEvent) {
+    /// Monitors often need a moment after showing up before they answer
+    const ATTEMPTS: u32 = 3;
+    const RETRY_DELAY: core::time::Duration = core::time::Duration::from_secs(1);
+    match event.kind {
+        // The handles opened before the system was suspended may not work anymore
+        DisplayEventKind::Connected | DisplayEventKind::Resumed => {
+            if let Err(e) = driver.redetect() {
+                tracing::warn!("Unable to detect the displays again: {:?}", e);
+                return;
+            }
+        }
+        DisplayEventKind::Awake => (),
+        DisplayEventKind::Disconnected | DisplayEventKind::Asleep => return,
+    }
+    let state = RestoreState::load(state);
+    for attempt in 1..=ATTEMPTS {
+        match state.restore(driver, event.io_path) {
+            Ok(_) => return,
+            Err(e) => {
+                tracing::warn!(
+                    "Unable to restore {} (attempt {}/{}): {:?}",
+                    event.io_path,
+                    attempt,
+                    ATTEMPTS,
+                    e
+                );
+                if attempt < ATTEMPTS {
+                    std::thread::sleep(RETRY_DELAY);
+                }
+            }
+        }
+    }
+}
+
+/// Run the [`Op`] in `args` against `driver`, writing the results to `out`
+pub fn run<D: DDCDriverTrait>(driver: &D, args: Args, out: &mut impl Write) -> Result<()> {
+    run_with(driver, args, out, &|_| false)
+}
+
+/// [`run`], stopping fades early once `cancelled` returns true for a display
+pub fn run_with<D: DDCDriverTrait>(
+    driver: &D,
+    args: Args,
+    out: &mut impl Write,
+    cancelled: &dyn Fn(&DisplayIdent) -> bool,
+) -> Result<()> {
+    let mut records = Vec::new();
+    let changes = args.op.is_remembered();
+    match args.op {
+        Op::Detect { monitor } => {
+            for (display, identity) in displays(driver, &monitor, &args.config)? {
+                records.push(Record::detect(display.index, &identity));
+            }
+        }
+        Op::GetBrightness { monitor } => {
+            for (display, identity) in displays(driver, &monitor, &args.config)? {
+                let backlight = driver.backlight_get(&display)?;
+                records.push(Record::brightness(&identity, &backlight, args.unit));
+            }
+        }
+        Op::SetBrightness {
+            brightness,
+            monitor,
+            fade,
+        } => {
+            let targets = displays(driver, &monitor, &args.config)?;
+            records.extend(change_brightness(
+                driver,
+                &fade,
+                args.unit,
+                &args.config,
+                targets,
+                &|_| brightness,
+                cancelled,
+            )?);
+        }
+        Op::IncreaseBrightness {
+            amount,
+            monitor,
+            fade,
+        } => {
+            let targets = displays(driver, &monitor, &args.config)?;
+            let config = &args.config;
+            records.extend(change_brightness(
+                driver,
+                &fade,
+                args.unit,
+                config,
+                targets,
+                &|identity| {
+                    BrightnessChange::Increase(amount.unwrap_or_else(|| config.step(identity)))
+                },
+                cancelled,
+            )?);
+        }
+        Op::DecreaseBrightness {
+            amount,
+            monitor,
+            fade,
+        } => {
+            let targets = displays(driver, &monitor, &args.config)?;
+            let config = &args.config;
+            records.extend(change_brightness(
+                driver,
+                &fade,
+                args.unit,
+                config,
+                targets,
+                &|identity| {
+                    BrightnessChange::Decrease(amount.unwrap_or_else(|| config.step(identity)))
+                },
+                cancelled,
+            )?);
+        }
+        Op::GetInput { monitor } => {
+            for (display, identity) in displays(driver, &monitor, &args.config)? {
+                let input = driver.input(&display)?;
+                records.push(Record::input(&identity, input));
+            }
+        }
+        Op::SetInput { monitor, input } => {
+            let mut targets = Vec::new();
+            for (display, identity) in displays(driver, &monitor, &args.config)? {
+                let configured = args.config.monitor(&identity).and_then(|(_, m)| m.input);
+                let Some(input) = input.or(configured) else {
+                    tracing::error!("No input given, and none configured for {}", identity.model);
+                    return Err(DDCError::new(DdcutilErrorKind::Other));
+                };
+                targets.push((display, identity, input));
+            }
+            for (display, identity, input) in targets {
+                driver.set_input(&display, input)?;
+                let input = driver.input(&display)?;
+                records.push(Record::set_input(&identity, input));
+            }
+        }
+        Op::Capabilities { monitor, raw } => {
+            for (display, identity) in displays(driver, &monitor, &args.config)? {
+                let capabilities = driver.capabilities(&display)?;
+                if raw {
+                    records.push(Record::raw_capabilities(&identity, capabilities));
+                } else {
+                    records.push(Record::capabilities(
+                        &identity,
+                        Capabilities::parse(&capabilities)?,
+                    ));
+                }
+            }
+        }
+        Op::Contrast { op } => records.extend(change_level(
+            driver,
+            op,
+            args.unit,
+            &args.config,
+            |display| driver.contrast_get(display),
+            |display, value| driver.contrast_set(display, value),
+            Record::contrast,
+        )?),
+        Op::Volume { op } => records.extend(change_level(
+            driver,
+            op,
+            args.unit,
+            &args.config,
+            |display| driver.volume_get(display),
+            |display, value| driver.volume_set(display, value),
+            Record::volume,
+        )?),
+        Op::Mute { state, monitor } => {
+            let targets = displays(driver, &monitor, &args.config)?;
+            // Check every monitor supports it before changing any
+            let mut values = Vec::new();
+            for (display, _) in &targets {
+                values.push(match state {
+                    MuteState::Toggle => !driver.muted(display)?,
+                    state => {
+                        driver.require(display, VCPFeature::MUTE)?;
+                        state == MuteState::On
+                    }
+                });
+            }
+            for ((display, identity), muted) in targets.iter().zip(values) {
+                driver.set_muted(display, muted)?;
+                records.push(Record::mute(identity, muted));
+            }
+        }
+        Op::Power { op, monitor } => {
+            let mode = match op {
+                PowerOp::Get => None,
+                PowerOp::On => Some(PowerMode::On),
+                PowerOp::Standby => Some(PowerMode::Standby),
+                PowerOp::Suspend => Some(PowerMode::Suspend),
+                PowerOp::Off => Some(PowerMode::Off),
+            };
+            for (display, identity) in displays(driver, &monitor, &args.config)? {
+                let power = match mode {
+                    Some(mode) => {
+                        driver.set_power(&display, mode)?;
+                        // Monitors going off may not answer anymore
+                        mode
+                    }
+                    None => driver.power(&display)?,
+                };
+                records.push(Record::power(&identity, power));
+            }
+        }
+        Op::Profile { op } => {
+            let Some(dir) = args.profiles.clone().or_else(Profile::dir) else {
+                tracing::error!("There is nowhere to keep the profiles");
+                return Err(DDCError::new(DdcutilErrorKind::Other));
+            };
+            match op {
+                ProfileOp::Save {
+                    name,
+                    monitor,
+                    features,
+                } => {
+                    let targets = displays(driver, &monitor, &args.config)?;
+                    let path = Profile::path(&dir, &name)?;
+                    records.extend(save_profile(driver, &path, targets, &features)?);
+                }
+                ProfileOp::Apply { name, monitor } => {
+                    let targets = displays(driver, &monitor, &args.config)?;
+                    let path = Profile::path(&dir, &name)?;
+                    records.extend(apply_profile(driver, &path, targets)?);
+                }
+            }
+        }
+        Op::Watch { restore: restoring } => {
+            let state = match (restoring, &args.state) {
+                (false, _) => None,
+                (true, Some(state)) => Some(state),
+                (true, None) => {
+                    tracing::error!("There is no state to restore the displays from");
+                    return Err(DDCError::new(DdcutilErrorKind::Other));
+                }
+            };
+            let connected = driver
+                .probe()
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|display| {
+                    let identity = driver.identify(&display).ok()?;
+                    Some(DisplayEvent {
+                        kind: DisplayEventKind::Connected,
+                        drm: identity.drm,
+                        io_path: display.io_path,
+                    })
+                })
+                .collect();
+            for event in driver.watch()?.with_resume(connected) {
+                args.output.print_event(&event, out)?;
+                if let Some(state) = state {
+                    restore(driver, state, &event);
+                }
+            }
+            // The events were printed as they came, there are no records
+            return Ok(());
+        }
+        Op::Daemon { socket } => {
+            Daemon::new(socket.unwrap_or_else(Daemon::socket))
+                .with_state(args.state.clone())
+                .with_config(args.config.clone())
+                .serve(driver)?;
+        }
+        #[cfg(feature = "dbus")]
+        Op::Dbus { address } => {
+            crate::dbus::serve(driver, address.as_deref())?;
+        }
+        Op::Completions { shell } => {
+            completions(shell, out);
+            // The script is the whole output
+            return Ok(());
+        }
+    }
+    if let Some(state) = args.state.as_deref().filter(|_| changes) {
+        remember(state, &records);
+    }
+    args.output.print(&records, out)?;
+    let failures = records.iter().filter(|record| record.error.is_some());
+    match failures.count() {
+        0 => Ok(()),
+        count => {
+            tracing::error!("{} features failed", count);
+            Err(DDCError::new(DdcutilErrorKind::Other))
+        }
+    }
 }
@@ -0,0 +1,138 @@
+use crate::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// The settings read from `config.toml`
+///
+/// ```toml
+/// [monitors.desk]
+/// serial = "5KC2R83"
+/// step = 5
+/// min = 10
+/// input = "DP-1"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The monitors by alias, selected with `--monitor <alias>`
+    #[serde(default)]
+    pub monitors: BTreeMap<String, MonitorConfig>,
+}
+
+/// How to recognize a monitor and its defaults
+///
+/// A monitor matches when every identifying field given is the one in its EDID, a monitor
+/// without any of them never matches.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MonitorConfig {
+    pub serial: Option<String>,
+    /// The model name, case insensitive
+    pub model: Option<String>,
+    /// Three letter PNP manufacturer id (eg. `DEL`), case insensitive
+    pub manufacturer: Option<String>,
+    /// Percent `inc` and `dec` change the brightness by when no amount is given
+    pub step: Option<u16>,
+    /// Lowest brightness in percent `set`, `inc` and `dec` go to
+    pub min: Option<u16>,
+    /// Highest brightness in percent `set`, `inc` and `dec` go to
+    pub max: Option<u16>,
+    /// The input `set-input` switches to when none is given
+    pub input: Option<Input>,
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/ddcbacklight/config.toml`, falling back to `~/.config`
+    pub fn path() -> Option<PathBuf> {
+        Some(config_dir()?.join("config.toml"))
+    }
+
+    /// Read the config, the default one if it doesn't exist
+    ///
+    /// Fails if the brightness limits of a monitor aren't `min <= max <= 100`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = match std::fs::read_to_string(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                tracing::error!("Unable to read the config {}: {}", path.display(), e);
+                return Err(e.into());
+            }
+        };
+        let invalid = |e: String| {
+            tracing::error!("Invalid config {}: {}", path.display(), e);
+            DDCError::from(std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        };
+        let config: Self = toml::from_str(&file).map_err(|e| invalid(e.to_string()))?;
+        for (alias, monitor) in &config.monitors {
+            let (min, max) = (monitor.min.unwrap_or(0), monitor.max.unwrap_or(100));
+            if max > 100 {
+                return Err(invalid(format!("max of monitor {} is above 100%", alias)));
+            }
+            if min > max {
+                return Err(invalid(format!(
+                    "min of monitor {} is above its max",
+                    alias
+                )));
+            }
+        }
+        Ok(config)
+    }
+
+    /// The alias and config of the first monitor matching `identity`
+    pub fn monitor(&self, identity: &DisplayIdentity) -> Option<(&str, &MonitorConfig)> {
+        self.monitors
+            .iter()
+            .find(|(_, monitor)| monitor.matches(identity))
+            .map(|(alias, monitor)| (alias.as_str(), monitor))
+    }
+
+    /// What `inc` and `dec` change the brightness of a monitor by, 10% unless configured
+    pub fn step(&self, identity: &DisplayIdentity) -> BrightnessAmount {
+        let step = self.monitor(identity).and_then(|(_, monitor)| monitor.step);
+        BrightnessAmount {
+            value: step.unwrap_or(10),
+            unit: BrightnessUnit::Percent,
+        }
+    }
+
+    /// The raw brightness of a monitor closest to `value` within its configured min and max
+    pub fn clamp(&self, identity: &DisplayIdentity, backlight: &Backlight, value: u16) -> u16 {
+        let Some((_, monitor)) = self.monitor(identity) else {
+            return value;
+        };
+        let min = monitor.min.map_or(0, |min| backlight.from_percent(min));
+        let max = monitor
+            .max
+            .map_or(backlight.max, |max| backlight.from_percent(max));
+        value.min(max).max(min)
+    }
+}
+
+impl MonitorConfig {
+    /// The selector matching the same monitors as [`MonitorConfig::matches`]
+    pub fn selector(&self) -> DisplaySelector {
+        let terms = [
+            self.serial.clone().map(DisplaySelector::Serial),
+            self.model.clone().map(DisplaySelector::Model),
+            self.manufacturer.clone().map(DisplaySelector::Manufacturer),
+        ];
+        match terms.into_iter().flatten().collect::<Vec<_>>() {
+            terms if terms.is_empty() => DisplaySelector::Or(terms),
+            terms => DisplaySelector::all(terms),
+        }
+    }
+
+    pub fn matches(&self, identity: &DisplayIdentity) -> bool {
+        let same = |expected: &Option<String>, actual: &str, ignore_case: bool| match expected {
+            Some(expected) if ignore_case => expected.trim().eq_ignore_ascii_case(actual.trim()),
+            Some(expected) => expected.trim() == actual.trim(),
+            None => true,
+        };
+        (self.serial.is_some() || self.model.is_some() || self.manufacturer.is_some())
+            && same(&self.serial, &identity.serial, false)
+            && same(&self.model, &identity.model, true)
+            && same(&self.manufacturer, &identity.manufacturer, true)
+    }
+}
@@ -0,0 +1,370 @@
+use crate::cli::{self, Args, FadeArgs, MonitorIdentifier, Op};
+use crate::*;
+use clap::Parser;
+use core::cell::RefCell;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::mpsc;
+
+/// A command sent to the daemon, as a single line of json
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Request {
+    /// The regular command line arguments, without the program name
+    pub args: Vec<String>,
+    /// Whether the output should be colored
+    pub color: bool,
+}
+
+/// The reply of the daemon to a [`Request`], as a single line of json
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Response {
+    /// What the command would have printed
+    pub output: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ResponseError>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResponseError {
+    pub message: String,
+    /// The `DDCRC_*` status of errors from the monitor
+    pub status: Option<i32>,
+}
+
+/// A long running process that keeps the displays open and runs the commands of the cli
+///
+/// Requests are read on a separate thread and run one after the other, so writes to a display
+/// never race. Bursts of brightness changes (eg. a held down key) that queued up while a command
+/// was running are merged into a single change, and a fade in progress stops as soon as another
+/// brightness change of the same display arrives.
+#[derive(Debug, Clone)]
+pub struct Daemon {
+    socket: PathBuf,
+    /// See [`Args::state`]
+    state: Option<PathBuf>,
+    config: Config,
+}
+
+/// A request waiting to be run, with the clients waiting for its result
+struct Job {
+    args: Args,
+    color: bool,
+    clients: Vec<UnixStream>,
+}
+
+impl Daemon {
+    pub fn new(socket: impl Into<PathBuf>) -> Self {
+        Self {
+            socket: socket.into(),
+            state: None,
+            config: Config::default(),
+        }
+    }
+
+    /// Remember the values set by the commands in `state`, see [`Args::state`]
+    pub fn with_state(mut self, state: Option<PathBuf>) -> Self {
+        self.state = state;
+        self
+    }
+
+    /// Run the commands with the monitor aliases and defaults of `config`
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// `$XDG_RUNTIME_DIR/ddcbacklight/daemon.sock`
+    pub fn socket() -> PathBuf {
+        runtime_dir().join("daemon.sock")
+    }
+
+    /// Serve requests until the process is killed
+    pub fn serve<D: DDCDriverTrait>(&self, driver: &D) -> Result<()> {
+        let listener = self.bind()?;
+        tracing::info!("Listening on {}", self.socket.display());
+        let (jobs, queue) = mpsc::channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Some(job) = stream.ok().and_then(read_request) else {
+                    continue;
+                };
+                if jobs.send(job).is_err() {
+                    break;
+                }
+            }
+        });
+        // Jobs received but not started, and how many of them change the brightness of each
+        // display: a fade stops when a newer change of its display is waiting
+        let waiting = RefCell::new(Vec::new());
+        let changes = RefCell::new(HashMap::<IOPath, usize>::new());
+        let receive = |job: Job| {
+            for io_path in self.changed_displays(driver, &job.args.op) {
+                *changes.borrow_mut().entry(io_path).or_default() += 1;
+            }
+            waiting.borrow_mut().push(job);
+        };
+        loop {
+            if waiting.borrow().is_empty() {
+                match queue.recv() {
+                    Ok(job) => receive(job),
+                    Err(_) => break,
+                }
+            }
+            queue.try_iter().for_each(&receive);
+            let batch = waiting.take();
+            changes.borrow_mut().clear();
+            for mut job in coalesce(batch) {
+                job.args.state.clone_from(&self.state);
+                job.args.config.clone_from(&self.config);
+                job.run(driver, &|display| {
+                    queue.try_iter().for_each(&receive);
+                    changes.borrow().contains_key(&display.io_path)
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// The displays whose brightness `op` changes
+    fn changed_displays<D: DDCDriverTrait>(&self, driver: &D, op: &Op) -> Vec<IOPath> {
+        let monitor = match op {
+            Op::SetBrightness { monitor, .. }
+            | Op::IncreaseBrightness { monitor, .. }
+            | Op::DecreaseBrightness { monitor, .. } => monitor,
+            _ => return Vec::new(),
+        };
+        match cli::displays(driver, monitor, &self.config) {
+            Ok(displays) => displays
+                .into_iter()
+                .map(|(display, _)| display.io_path)
+                .collect(),
+            Err(e) => {
+                tracing::debug!("Unable to find the displays of {:?}: {:?}", op, e);
+                Vec::new()
+            }
+        }
+    }
+
+    fn bind(&self) -> Result<UnixListener> {
+        if UnixStream::connect(&self.socket).is_ok() {
+            tracing::error!("A daemon is already listening on {}", self.socket.display());
+            return Err(std::io::Error::from(std::io::ErrorKind::AddrInUse).into());
+        }
+        // Left behind by a daemon that was killed
+        let _ = std::fs::remove_file(&self.socket);
+        match self.socket.parent() {
+            Some(dir) if dir == runtime_dir() => private_dir(dir)?,
+            Some(dir) => std::fs::create_dir_all(dir)?,
+            None => {}
+        }
+        let listener = UnixListener::bind(&self.socket)?;
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&self.socket, std::fs::Permissions::from_mode(0o600))?;
+        Ok(listener)
+    }
+
+    /// Run `args` in the daemon, `None` if no daemon of the current user is listening on the
+    /// socket
+    pub fn request(&self, args: &[String], color: bool) -> Option<Result<String>> {
+        use std::os::unix::fs::MetadataExt;
+        let owner = std::fs::symlink_metadata(&self.socket).ok()?.uid();
+        if owner != uid() {
+            tracing::warn!(
+                "Ignoring {}, it belongs to another user",
+                self.socket.display()
+            );
+            return None;
+        }
+        let stream = UnixStream::connect(&self.socket).ok()?;
+        tracing::info!(
+            "Sending the command to the daemon on {}",
+            self.socket.display()
+        );
+        let request = Request {
+            args: args.to_vec(),
+            color,
+        };
+        Some(
+            send(stream, &request).and_then(|response| match response.error {
+                None => Ok(response.output),
+                Some(error) => {
+                    tracing::error!("The daemon failed to run the command: {}", error.message);
+                    Err(DDCError::new(match error.status {
+                        Some(status) => DdcutilErrorKind::LibDDCUtilError(status.into()),
+                        None => DdcutilErrorKind::Other,
+                    }))
+                }
+            }),
+        )
+    }
+}
+
+fn send(mut stream: UnixStream, request: &Request) -> Result<Response> {
+    let mut line = serde_json::to_string(request).map_err(std::io::Error::from)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    Ok(serde_json::from_str(&reply).map_err(std::io::Error::from)?)
+}
+
+/// Read the request of a client, replying right away if it isn't valid
+fn read_request(stream: UnixStream) -> Option<Job> {
+    // Don't let a client that never sends anything block the others
+    let _ = stream.set_read_timeout(Some(core::time::Duration::from_secs(1)));
+    let mut line = String::new();
+    let parsed = BufReader::new(&stream)
+        .read_line(&mut line)
+        .map_err(|e| e.to_string())
+        .and_then(|_| serde_json::from_str::<Request>(&line).map_err(|e| e.to_string()))
+        .and_then(|request| {
+            let args = core::iter::once("xbacklight".to_string()).chain(request.args);
+            match Args::try_parse_from(args) {
+                Ok(args) if args.op.is_local() => {
+                    Err("the command can't run in the daemon".to_string())
+                }
+                Ok(args) => Ok((args, request.color)),
+                Err(e) => Err(e.to_string()),
+            }
+        });
+    match parsed {
+        Ok((args, color)) => Some(Job {
+            args,
+            color,
+            clients: vec![stream],
+        }),
+        Err(message) => {
+            tracing::warn!("Invalid request: {}", message);
+            let response = Response {
+                output: String::new(),
+                error: Some(ResponseError {
+                    message,
+                    status: None,
+                }),
+            };
+            reply(vec![stream], &response);
+            None
+        }
+    }
+}
+
+fn reply(clients: Vec<UnixStream>, response: &Response) {
+    let Ok(mut line) = serde_json::to_string(response) else {
+        return;
+    };
+    line.push('\n');
+    for mut client in clients {
+        if let Err(e) = client.write_all(line.as_bytes()) {
+            tracing::warn!("Unable to reply to a client: {}", e);
+        }
+    }
+}
+
+impl Job {
+    fn run<D: DDCDriverTrait>(self, driver: &D, cancelled: &dyn Fn(&DisplayIdent) -> bool) {
+        colored::control::set_override(self.color);
+        let mut output = Vec::new();
+        let result = cli::run_with(driver, self.args, &mut output, cancelled);
+        colored::control::unset_override();
+        let response = Response {
+            output: String::from_utf8_lossy(&output).into_owned(),
+            error: result.err().map(|e| ResponseError {
+                message: e.kind().to_string(),
+                status: match e.kind() {
+                    DdcutilErrorKind::LibDDCUtilError(e) => Some(e.status()),
+                    _ => None,
+                },
+            }),
+        };
+        reply(self.clients, &response);
+    }
+}
+
+/// Merge the jobs that follow each other when they can be done at once
+fn coalesce(batch: Vec<Job>) -> Vec<Job> {
+    let mut out: Vec<Job> = Vec::new();
+    for job in batch {
+        if let Some(last) = out.last_mut() {
+            if last.color == job.color {
+                if let Some(args) = Daemon::merge(&last.args, &job.args) {
+                    tracing::debug!("Merged {:?} into {:?}", job.args.op, last.args.op);
+                    last.args = args;
+                    last.clients.extend(job.clients);
+                    continue;
+                }
+            }
+        }
+        out.push(job);
+    }
+    out
+}
+
+impl Daemon {
+    /// A single command with the effect of running `first` and then `next`, if there is one
+    ///
+    /// Only brightness changes of the same displays are merged: relative changes in the same unit
+    /// add up, and an absolute one replaces whatever came before it.
+    pub fn merge(first: &Args, next: &Args) -> Option<Args> {
+        let (a, monitor, fade) = brightness_change(&first.op)?;
+        let (b, next_monitor, next_fade) = brightness_change(&next.op)?;
+        if monitor != next_monitor
+            || fade != next_fade
+            || first.unit != next.unit
+            || first.output != next.output
+        {
+            return None;
+        }
+        let signed = |change| match change {
+            BrightnessChange::Increase(amount) => Some((amount.unit, i32::from(amount.value))),
+            BrightnessChange::Decrease(amount) => Some((amount.unit, -i32::from(amount.value))),
+            _ => None,
+        };
+        let change = match (signed(a), signed(b)) {
+            (_, None) => b,
+            (Some((unit, a)), Some((next_unit, b))) if unit == next_unit => {
+                let sum = a + b;
+                let amount = BrightnessAmount {
+                    value: sum.unsigned_abs().min(u16::MAX.into()) as u16,
+                    unit,
+                };
+                match sum < 0 {
+                    true => BrightnessChange::Decrease(amount),
+                    false => BrightnessChange::Increase(amount),
+                }
+            }
+            _ => return None,
+        };
+        Some(Args {
+            op: Op::SetBrightness {
+                brightness: change,
+                monitor: monitor.clone(),
+                fade: fade.clone(),
+            },
+            ..next.clone()
+        })
+    }
+}
+
+fn brightness_change(op: &Op) -> Option<(BrightnessChange, &MonitorIdentifier, &FadeArgs)> {
+    match op {
+        Op::SetBrightness {
+            brightness,
+            monitor,
+            fade,
+        } => Some((*brightness, monitor, fade)),
+        Op::IncreaseBrightness {
+            amount,
+            monitor,
+            fade,
+        } => Some((BrightnessChange::Increase((*amount)?), monitor, fade)),
+        Op::DecreaseBrightness {
+            amount,
+            monitor,
+            fade,
+        } => Some((BrightnessChange::Decrease((*amount)?), monitor, fade)),
+        _ => None,
+    }
+}
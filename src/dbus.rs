@@ -0,0 +1,172 @@
+use crate::*;
+use std::sync::mpsc::{self, Sender};
+use zbus::fdo;
+use zbus::object_server::SignalEmitter;
+
+/// Well known name of the service on the bus
+pub const BUS_NAME: &str = "org.ddcbacklight.DDCBacklight";
+/// Interface of the object published for each display
+pub const DISPLAY_INTERFACE: &str = "org.ddcbacklight.Display";
+
+/// Object path of a display, eg. `/org/ddcbacklight/display/i2c_4`
+pub fn object_path(io_path: &IOPath) -> String {
+    match io_path {
+        IOPath::I2C(bus) => format!("/org/ddcbacklight/display/i2c_{}", bus),
+        IOPath::Usb(dev) => format!("/org/ddcbacklight/display/hiddev{}", dev),
+    }
+}
+
+/// A request from the bus for the thread that owns the driver, answered on an async channel so
+/// the executor of the connection isn't blocked while a monitor is slow to answer
+enum Call {
+    Get(u8, async_channel::Sender<Result<VCPFeature>>),
+    Set(u8, u16, async_channel::Sender<Result<()>>),
+}
+
+/// Publish one object per display on the session bus, or the bus at `address`
+///
+/// The drivers can't be shared between threads, so the objects forward every read and write to
+/// the calling thread, which serves them until the connection to the bus is closed.
+pub fn serve<D: DDCDriverTrait>(driver: &D, address: Option<&str>) -> Result<()> {
+    let (calls, queue) = mpsc::channel();
+    let mut builder = match address {
+        Some(address) => zbus::blocking::connection::Builder::address(address)?,
+        None => zbus::blocking::connection::Builder::session()?,
+    };
+    let displays = driver.probe()?;
+    for (index, display) in displays.iter().enumerate() {
+        let identity = driver.identify(display)?;
+        let path = object_path(&display.io_path);
+        tracing::info!("Publishing {} at {}", identity.model, path);
+        builder = builder.serve_at(
+            path,
+            DisplayObject {
+                index,
+                identity,
+                calls: calls.clone(),
+            },
+        )?;
+    }
+    // Only the objects hold a sender now, so the loop ends with the connection
+    drop(calls);
+    let _connection = builder.name(BUS_NAME)?.build()?;
+    tracing::info!("Serving {} displays as {}", displays.len(), BUS_NAME);
+    for (index, call) in queue {
+        let display = &displays[index];
+        match call {
+            Call::Get(code, reply) => {
+                let _ = reply.send_blocking(driver.get_vcp(display, code));
+            }
+            Call::Set(code, value, reply) => {
+                let _ = reply.send_blocking(driver.set_vcp(display, code, value));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The object published for a display
+struct DisplayObject {
+    /// Index of the display in the list probed by [`serve`]
+    index: usize,
+    identity: DisplayIdentity,
+    calls: Sender<(usize, Call)>,
+}
+
+impl DisplayObject {
+    async fn get(&self, code: u8) -> fdo::Result<VCPFeature> {
+        let (reply, result) = async_channel::bounded(1);
+        self.call(Call::Get(code, reply))?;
+        result.recv().await.map_err(closed)?.map_err(failed)
+    }
+
+    async fn set(&self, code: u8, value: u16) -> fdo::Result<()> {
+        let (reply, result) = async_channel::bounded(1);
+        self.call(Call::Set(code, value, reply))?;
+        result.recv().await.map_err(closed)?.map_err(failed)
+    }
+
+    fn call(&self, call: Call) -> fdo::Result<()> {
+        self.calls.send((self.index, call)).map_err(closed)
+    }
+}
+
+fn failed(e: DDCError) -> fdo::Error {
+    fdo::Error::Failed(e.kind().to_string())
+}
+
+fn closed(e: impl core::fmt::Display) -> fdo::Error {
+    fdo::Error::Failed(format!("The service is shutting down: {}", e))
+}
+
+#[zbus::interface(name = "org.ddcbacklight.Display")]
+impl DisplayObject {
+    #[zbus(property)]
+    fn model(&self) -> String {
+        self.identity.model.clone()
+    }
+
+    /// The drm connector without the card prefix, eg. `DP-1`
+    #[zbus(property)]
+    fn connector(&self) -> String {
+        self.identity.connector().to_string()
+    }
+
+    #[zbus(property)]
+    fn serial(&self) -> String {
+        self.identity.serial.clone()
+    }
+
+    /// Brightness in percent of the max reported by the monitor
+    #[zbus(property)]
+    async fn brightness(&self) -> fdo::Result<u16> {
+        Ok(Backlight::from(self.get(VCPFeature::BACKLIGHT).await?).percent())
+    }
+
+    #[zbus(property)]
+    async fn set_brightness(&self, percent: u16) -> fdo::Result<()> {
+        let backlight = Backlight::from(self.get(VCPFeature::BACKLIGHT).await?);
+        let raw = backlight
+            .raw(BrightnessUnit::Percent, percent)
+            .map_err(|e| fdo::Error::InvalidArgs(e.kind().to_string()))?;
+        self.set(VCPFeature::BACKLIGHT, raw).await
+    }
+
+    /// The input source, by name (eg. `DP-1`, `HDMI-2`) or as a raw value
+    #[zbus(property)]
+    async fn input(&self) -> fdo::Result<String> {
+        Ok(Input::from(self.get(VCPFeature::INPUT).await?.sl).to_string())
+    }
+
+    #[zbus(property)]
+    async fn set_input(&self, input: String) -> fdo::Result<()> {
+        let value = input
+            .parse::<Input>()
+            .and_then(u8::try_from)
+            .map_err(|e| fdo::Error::InvalidArgs(e.kind().to_string()))?;
+        self.set(VCPFeature::INPUT, value.into()).await
+    }
+
+    /// Read a non-table VCP feature, returns the current and the max value
+    #[zbus(out_args("current", "max"))]
+    async fn get_vcp(&self, code: u8) -> fdo::Result<(u16, u16)> {
+        let feature = self.get(code).await?;
+        Ok((feature.current, feature.max))
+    }
+
+    /// Write a non-table VCP feature
+    async fn set_vcp(
+        &self,
+        code: u8,
+        value: u16,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> fdo::Result<()> {
+        self.set(code, value).await?;
+        match code {
+            VCPFeature::BACKLIGHT => self.brightness_changed(&emitter).await?,
+            VCPFeature::INPUT => self.input_changed(&emitter).await?,
+            _ => (),
+        }
+        Ok(())
+    }
+}
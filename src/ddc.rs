@@ -1,43 +1,36 @@
+use crate::ascii::AsAscii;
 use crate::error::*;
+use crate::*;
+use core::cell::{Cell, OnceCell, RefCell};
 use core::ptr::{null_mut, NonNull};
 use ddcutil_sys::bindings::*;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Mutex, MutexGuard, PoisonError};
 
-#[derive(Debug, Clone, Copy)]
-#[allow(warnings)]
-pub enum Input {
-    HDMI(u8),
-    DP(u8),
-    TYPEC(u8),
+/// The null terminated array returned by `ddca_get_display_refs`
+///
+/// The array is owned by us while the display refs in it are owned by libddcutil.
+#[derive(Debug)]
+struct DisplayRefs {
+    list: NonNull<DDCA_Display_Ref>,
+    len: usize,
 }
 
-impl From<Input> for u8 {
-    fn from(input: Input) -> u8 {
-        match input {
-            Input::HDMI(1) => 0x11,
-            Input::HDMI(2) => 0x12,
-            Input::DP(1) => 0x0f,
-            Input::DP(2) => 0x10,
-            _ => todo!(),
-        }
-    }
-}
-impl TryFrom<u8> for Input {
-    type Error = DDCError;
-    fn try_from(value: u8) -> std::result::Result<Self, Self::Error> {
-        Ok(match value {
-            0x11 => Self::HDMI(1),
-            0x12 => Self::HDMI(2),
-            0x0f => Self::DP(1),
-            0x10 => Self::DP(2),
-            _ => Err(DDCError::new(DdcutilErrorKind::Other))?,
-        })
+impl Drop for DisplayRefs {
+    fn drop(&mut self) {
+        unsafe { libc::free(self.list.as_ptr().cast()) };
     }
 }
 
+/// The displays found by libddcutil
+///
+/// Every [`Display`] opened from this list keeps the underlying display refs alive.
+#[derive(Debug, Clone)]
 pub struct DisplayList {
-    list: NonNull<DDCA_Display_Ref>,
-    len: usize,
-    // handle: NonNull<DDCA_Display_Handle>,
+    refs: Rc<DisplayRefs>,
 }
 
 impl DisplayList {
@@ -63,25 +56,43 @@ impl DisplayList {
         };
 
         Ok(Self {
-            list: NonNull::new(drefs)
-                .ok_or_else(|| DDCError::new(DdcutilErrorKind::UnknownHandle))?,
-            len: dlist_count,
+            refs: Rc::new(DisplayRefs {
+                list: NonNull::new(drefs)
+                    .ok_or_else(|| DDCError::new(DdcutilErrorKind::UnknownHandle))?,
+                len: dlist_count,
+            }),
         })
     }
 
+    pub fn len(&self) -> usize {
+        self.refs.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.refs.len == 0
+    }
+
     pub fn get(&self, index: usize) -> Result<DisplayInfo<'_>> {
-        if index < self.len {
+        if index < self.refs.len {
             let mut info2: *mut DDCA_Display_Info2 = null_mut();
-            let dref: *mut DDCA_Display_Ref = unsafe { self.list.as_ptr().offset(index as isize) };
+            let dref: *mut DDCA_Display_Ref = unsafe { self.refs.list.as_ptr().add(index) };
             let rc = unsafe { ddca_get_display_info2(*dref, &mut info2) };
             LibDDCUtilError::from_rc(rc)?;
-            let info2 = unsafe { &*info2 };
-            Ok(DisplayInfo { info: info2 })
+            Ok(DisplayInfo {
+                info: NonNull::new(info2)
+                    .ok_or_else(|| DDCError::new(DdcutilErrorKind::UnknownHandle))?,
+                list: Some(self),
+            })
         } else {
             Err(DDCError::new(DdcutilErrorKind::OutOfRange))
         }
     }
 
+    /// The index of the display `dref` refers to
+    fn position(&self, dref: DDCA_Display_Ref) -> Option<usize> {
+        (0..self.refs.len).find(|&index| unsafe { *self.refs.list.as_ptr().add(index) } == dref)
+    }
+
     pub fn iter(&self) -> DisplayListIter<'_> {
         DisplayListIter {
             list: self,
@@ -98,7 +109,7 @@ pub struct DisplayListIter<'dl> {
 impl<'i> Iterator for DisplayListIter<'i> {
     type Item = DisplayInfo<'i>;
     fn next(&mut self) -> Option<Self::Item> {
-        if self.index < self.list.len {
+        if self.index < self.list.len() {
             let out = self
                 .list
                 .get(self.index)
@@ -118,9 +129,33 @@ impl<'i> Iterator for DisplayListIter<'i> {
     }
 }
 
+/// Information about a single display, freed when dropped
 #[derive(Debug)]
-pub struct DisplayInfo<'info> {
-    info: &'info DDCA_Display_Info2,
+pub struct DisplayInfo<'list> {
+    info: NonNull<DDCA_Display_Info2>,
+    /// The list the display is from, `None` for display refs owned by libddcutil
+    list: Option<&'list DisplayList>,
+}
+
+impl Drop for DisplayInfo<'_> {
+    fn drop(&mut self) {
+        unsafe { ddca_free_display_info2(self.info.as_ptr()) };
+    }
+}
+
+impl DisplayInfo<'static> {
+    /// Information about a display ref libddcutil found by itself (eg. through
+    /// `ddca_get_display_ref`)
+    fn from_ref(dref: DDCA_Display_Ref) -> Result<Self> {
+        let mut info2: *mut DDCA_Display_Info2 = null_mut();
+        let rc = unsafe { ddca_get_display_info2(dref, &mut info2) };
+        LibDDCUtilError::from_rc(rc)?;
+        Ok(Self {
+            info: NonNull::new(info2)
+                .ok_or_else(|| DDCError::new(DdcutilErrorKind::UnknownHandle))?,
+            list: None,
+        })
+    }
 }
 
 impl DisplayInfo<'_> {
@@ -128,120 +163,304 @@ impl DisplayInfo<'_> {
         Display::open(self)
     }
 
+    fn info(&self) -> &DDCA_Display_Info2 {
+        unsafe { self.info.as_ref() }
+    }
+
     pub fn io_path(&self) -> IOPath {
-        self.info.path.into()
+        self.info().path.into()
     }
 
     pub fn model(&self) -> &str {
-        unsafe { core::ffi::CStr::from_ptr(self.info.model_name.as_ptr()) }
+        unsafe { core::ffi::CStr::from_ptr(self.info().model_name.as_ptr()) }
             .to_str()
             .expect("Invalid UTF-8 in model name")
     }
 
     pub fn drm(&self) -> String {
-        let out = String::from_utf8_lossy(
-            self.info
-                .drm_card_connector
-                .iter()
-                .map(|&c| c as u8)
-                .take_while(|&c| c != 0)
-                .collect::<Vec<u8>>()
-                .as_slice(),
-        )
-        .to_string();
-        out
+        lossy(&self.info().drm_card_connector)
         // unsafe { core::ffi::CStr::from_ptr(self.info.drm_card_connector.as_ptr()) }
         //     .to_str()
         //     .ok()
     }
+
+    /// Three letter PNP manufacturer id
+    pub fn manufacturer(&self) -> String {
+        lossy(&self.info().mfg_id)
+    }
+
+    pub fn serial(&self) -> String {
+        lossy(&self.info().sn)
+    }
+
+    pub fn product_code(&self) -> u16 {
+        self.info().product_code
+    }
+
+    pub fn edid(&self) -> Option<Edid> {
+        Edid::parse(self.edid_bytes())
+    }
+
+    /// The raw 128 byte EDID
+    pub fn edid_bytes(&self) -> &[u8] {
+        &self.info().edid_bytes
+    }
+
+    /// The MCCS version the monitor reported, `None` if unknown
+    pub fn mccs_version(&self) -> Option<(u8, u8)> {
+        let version = self.info().vcp_version;
+        (version.major != 0).then_some((version.major, version.minor))
+    }
+
+    /// Whether ddcutil was able to communicate with the display
+    pub fn ddc_capable(&self) -> bool {
+        self.info().dispno > 0
+    }
+
+    pub fn identity(&self) -> DisplayIdentity {
+        DisplayIdentity {
+            model: self.model().to_string(),
+            drm: self.drm(),
+            io_path: self.io_path(),
+            manufacturer: self.manufacturer(),
+            serial: self.serial(),
+            product_code: self.product_code(),
+            year: self.edid().map(|edid| edid.year),
+            mccs_version: self
+                .mccs_version()
+                .map(|(major, minor)| format!("{}.{}", major, minor)),
+            ddc: self.ddc_capable(),
+        }
+    }
+}
+
+/// Read a nul terminated string from a fixed size field
+fn lossy(field: &[core::ffi::c_char]) -> String {
+    String::from_utf8_lossy(
+        field
+            .iter()
+            .map(|&c| c as u8)
+            .take_while(|&c| c != 0)
+            .collect::<Vec<u8>>()
+            .as_slice(),
+    )
+    .to_string()
 }
 
+/// An open display handle, closed when dropped
 #[derive(Debug)]
 pub struct Display {
     handle: DDCA_Display_Handle,
+    /// The display list the handle was opened from, if any
+    _refs: Option<Rc<DisplayRefs>>,
+}
+
+impl Drop for Display {
+    fn drop(&mut self) {
+        let rc = unsafe { ddca_close_display(self.handle) };
+        if let Err(e) = LibDDCUtilError::from_rc(rc) {
+            tracing::error!("Error closing display: {:?}", e);
+        }
+    }
 }
 
 impl Display {
-    const BACKLIGHT: u8 = 0x10;
-    const INPUT: u8 = 0x60;
     pub fn open(info: &DisplayInfo) -> Result<Self> {
-        let dref = info.info.dref;
+        let dref = info.info().dref;
         let mut dh = null_mut();
         let rc = unsafe { ddca_open_display2(dref, true, &mut dh) };
         LibDDCUtilError::from_rc(rc)?;
-        Ok(Self { handle: dh })
+        Ok(Self {
+            handle: dh,
+            _refs: info.list.map(|list| Rc::clone(&list.refs)),
+        })
     }
 
-    pub fn backlight_set(&self, value: u16) -> Result<()> {
-        if value > 100 {
-            return Err(DDCError::new(DdcutilErrorKind::OutOfRange));
-        }
-        let [hi_byte, lo_byte] = value.to_be_bytes();
-        tracing::trace!("Setting backlight to {} ({} {})", value, hi_byte, lo_byte);
-        let rc =
-            unsafe { ddca_set_non_table_vcp_value(self.handle, Self::BACKLIGHT, hi_byte, lo_byte) };
+    /// Open the display on i2c `bus` directly, without getting the display list first
+    pub fn open_bus(bus: i32) -> Result<Self> {
+        let mut did: DDCA_Display_Identifier = null_mut();
+        let rc = unsafe { ddca_create_busno_display_identifier(bus, &mut did) };
         LibDDCUtilError::from_rc(rc)?;
-        Ok(())
-    }
-
-    pub fn backlight_get(&self) -> Result<Backlight> {
-        let mut out = DDCA_Non_Table_Vcp_Value {
-            mh: 0,
-            ml: 0,
-            sh: 0,
-            sl: 0,
-        };
-        let rc = unsafe { ddca_get_non_table_vcp_value(self.handle, Self::BACKLIGHT, &mut out) };
+        let dref = DisplayIdentifier(did).display_ref()?;
+        let mut dh = null_mut();
+        let rc = unsafe { ddca_open_display2(dref, true, &mut dh) };
         LibDDCUtilError::from_rc(rc)?;
-        Ok(Backlight {
-            max: u16::from_be_bytes([out.mh, out.ml]),
-            current: u16::from_be_bytes([out.sh, out.sl]),
+        Ok(Self {
+            handle: dh,
+            _refs: None,
         })
     }
 
-    pub fn input(&self) -> Result<Input> {
+    /// Read a non-table VCP feature
+    pub fn get_vcp(&self, code: u8) -> Result<VCPFeature> {
         let mut out = DDCA_Non_Table_Vcp_Value {
             mh: 0,
             ml: 0,
             sh: 0,
             sl: 0,
         };
-        let rc = unsafe { ddca_get_non_table_vcp_value(self.handle, Self::INPUT, &mut out) };
+        let rc = unsafe { ddca_get_non_table_vcp_value(self.handle, code, &mut out) };
         LibDDCUtilError::from_rc(rc)?;
-        Ok(match (out.ml, out.sl) {
-            (_, 0x0f) => Input::DP(1),   // DP-1
-            (_, 0x10) => Input::DP(2),   // DP-2
-            (_, 0x11) => Input::HDMI(1), // HDMI-1
-            (_, 0x12) => Input::HDMI(2), // HDMI-2
-            _ => todo!(),
-        })
+        tracing::trace!(
+            "Got vcp {:#04x}: mh={} ml={} sh={} sl={}",
+            code,
+            out.mh,
+            out.ml,
+            out.sh,
+            out.sl
+        );
+        Ok(VCPFeature::from_bytes(code, out.mh, out.ml, out.sh, out.sl))
     }
-    pub fn set_input(&self, input: Input) -> Result<()> {
-        let value: u8 = input.into();
-        let rc = unsafe { ddca_set_non_table_vcp_value(self.handle, Self::INPUT, 0, value) };
+
+    /// Write a non-table VCP feature
+    pub fn set_vcp(&self, code: u8, value: u16) -> Result<()> {
+        let [hi_byte, lo_byte] = value.to_be_bytes();
+        tracing::trace!(
+            "Setting vcp {:#04x} to {} ({} {})",
+            code,
+            value,
+            hi_byte,
+            lo_byte
+        );
+        let rc = unsafe { ddca_set_non_table_vcp_value(self.handle, code, hi_byte, lo_byte) };
         LibDDCUtilError::from_rc(rc)?;
         Ok(())
     }
-}
 
-#[derive(Debug)]
-pub struct Backlight {
-    pub current: u16,
-    pub max: u16,
+    /// Read the raw MCCS capabilities string reported by the monitor
+    pub fn capabilities_string(&self) -> Result<String> {
+        let mut caps: *mut core::ffi::c_char = null_mut();
+        let rc = unsafe { ddca_get_capabilities_string(self.handle, &mut caps) };
+        LibDDCUtilError::from_rc(rc)?;
+        let out = caps.cast_const().as_ascii().to_string();
+        // The string is allocated by libddcutil and owned by the caller
+        unsafe { libc::free(caps.cast()) };
+        Ok(out)
+    }
+
+    /// Read and parse the capabilities of the monitor
+    pub fn capabilities(&self) -> Result<Capabilities> {
+        Capabilities::parse_ddcutil(&self.capabilities_string()?)
+    }
+
+    /// Write the raw brightness
+    pub fn backlight_set(&self, value: u16) -> Result<()> {
+        self.set_vcp(VCPFeature::BACKLIGHT, value)
+    }
+
+    pub fn backlight_get(&self) -> Result<Backlight> {
+        Ok(self.get_vcp(VCPFeature::BACKLIGHT)?.into())
+    }
+
+    /// Write the raw contrast
+    pub fn contrast_set(&self, value: u16) -> Result<()> {
+        self.set_vcp(VCPFeature::CONTRAST, value)
+    }
+
+    pub fn contrast_get(&self) -> Result<Contrast> {
+        Ok(self.get_vcp(VCPFeature::CONTRAST)?.into())
+    }
+
+    /// Write the raw volume
+    pub fn volume_set(&self, value: u16) -> Result<()> {
+        Capabilities::require(self.capabilities(), VCPFeature::VOLUME)?;
+        self.set_vcp(VCPFeature::VOLUME, value)
+    }
+
+    pub fn volume_get(&self) -> Result<Volume> {
+        Capabilities::require(self.capabilities(), VCPFeature::VOLUME)?;
+        Ok(self.get_vcp(VCPFeature::VOLUME)?.into())
+    }
+
+    pub fn muted(&self) -> Result<bool> {
+        Capabilities::require(self.capabilities(), VCPFeature::MUTE)?;
+        Ok(self.get_vcp(VCPFeature::MUTE)?.sl == 1)
+    }
+
+    pub fn set_muted(&self, muted: bool) -> Result<()> {
+        Capabilities::require(self.capabilities(), VCPFeature::MUTE)?;
+        self.set_vcp(VCPFeature::MUTE, if muted { 1 } else { 2 })
+    }
+
+    /// Read the power mode from VCP 0xD6, or 0xE1 on monitors only supporting that
+    pub fn power(&self) -> Result<PowerMode> {
+        let code = PowerMode::feature(self.capabilities())?;
+        PowerMode::try_from(&self.get_vcp(code)?)
+    }
+
+    /// Switch the power mode
+    ///
+    /// Monitors only supporting VCP 0xE1 can only be turned on or off, the ones listing their
+    /// VCP 0xD6 values can only be switched to those.
+    pub fn set_power(&self, mode: PowerMode) -> Result<()> {
+        let (code, value) = mode.setting(self.capabilities())?;
+        self.set_vcp(code, value)
+    }
+
+    pub fn input(&self) -> Result<Input> {
+        Ok(Input::from(self.get_vcp(VCPFeature::INPUT)?.sl))
+    }
+    pub fn set_input(&self, input: Input) -> Result<()> {
+        let value = u8::try_from(input)?;
+        self.set_vcp(VCPFeature::INPUT, value.into())
+    }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub enum IOPath {
-    I2C(i32),
-    Usb(i32),
+/// A display identifier, freed when dropped
+struct DisplayIdentifier(DDCA_Display_Identifier);
+
+impl Drop for DisplayIdentifier {
+    fn drop(&mut self) {
+        unsafe { ddca_free_display_identifier(self.0) };
+    }
 }
 
-impl core::fmt::Display for IOPath {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        match self {
-            Self::I2C(bus) => write!(f, "/dev/i2c-{}", bus),
-            Self::Usb(dev) => write!(f, "/dev/hiddev{}", dev),
+impl DisplayIdentifier {
+    /// The identifier libddcutil can find the single monitor matching `selector` with, if any
+    fn new(selector: &DisplaySelector) -> Result<Option<Self>> {
+        let mut did: DDCA_Display_Identifier = null_mut();
+        if let DisplaySelector::Edid(edid) = selector {
+            if edid.len() != Edid::LEN {
+                return Ok(None);
+            }
+            let rc = unsafe { ddca_create_edid_display_identifier(edid.as_ptr(), &mut did) };
+            LibDDCUtilError::from_rc(rc)?;
+            return Ok(Some(Self(did)));
         }
+        let Some((mfg, model, serial)) = selector.model_serial() else {
+            return Ok(None);
+        };
+        let string = |value: Option<&str>| {
+            value
+                .map(|value| std::ffi::CString::new(value.trim()))
+                .transpose()
+        };
+        let (Ok(mfg), Ok(model), Ok(serial)) = (string(mfg), string(model), string(Some(serial)))
+        else {
+            return Ok(None);
+        };
+        let pointer = |value: &Option<std::ffi::CString>| {
+            value
+                .as_ref()
+                .map_or(core::ptr::null(), |value| value.as_ptr())
+        };
+        let rc = unsafe {
+            ddca_create_mfg_model_sn_display_identifier(
+                pointer(&mfg),
+                pointer(&model),
+                pointer(&serial),
+                &mut did,
+            )
+        };
+        LibDDCUtilError::from_rc(rc)?;
+        Ok(Some(Self(did)))
+    }
+
+    fn display_ref(&self) -> Result<DDCA_Display_Ref> {
+        let mut dref: DDCA_Display_Ref = null_mut();
+        let rc = unsafe { ddca_get_display_ref(self.0, &mut dref) };
+        LibDDCUtilError::from_rc(rc)?;
+        Ok(dref)
     }
 }
 
@@ -258,6 +477,392 @@ impl From<DDCA_IO_Path> for IOPath {
     }
 }
 
+impl Capabilities {
+    /// Parse a capabilities string using libddcutil instead of [`Capabilities::parse`]
+    pub fn parse_ddcutil(raw: &str) -> Result<Self> {
+        let raw_c =
+            std::ffi::CString::new(raw).map_err(|_| DDCError::new(DdcutilErrorKind::Other))?;
+        let mut parsed: *mut DDCA_Capabilities = null_mut();
+        let rc = unsafe { ddca_parse_capabilities_string(raw_c.as_ptr().cast_mut(), &mut parsed) };
+        LibDDCUtilError::from_rc(rc)?;
+        let parsed =
+            NonNull::new(parsed).ok_or_else(|| DDCError::new(DdcutilErrorKind::UnknownHandle))?;
+        let caps = unsafe { parsed.as_ref() };
+        let slice = |ptr: *mut u8, len: core::ffi::c_int| match ptr.is_null() {
+            true => Vec::new(),
+            false => unsafe { core::slice::from_raw_parts(ptr, len as usize) }.to_vec(),
+        };
+        let features = match caps.vcp_codes.is_null() {
+            true => &[][..],
+            false => unsafe {
+                core::slice::from_raw_parts(caps.vcp_codes, caps.vcp_code_ct as usize)
+            },
+        };
+        let version = caps.version_spec;
+        let out = Self {
+            // libddcutil doesn't keep the model
+            model: Self::parse(raw).ok().and_then(|parsed| parsed.model),
+            mccs_version: (version.major != 0)
+                .then(|| format!("{}.{}", version.major, version.minor)),
+            commands: slice(caps.cmd_codes, caps.cmd_ct),
+            features: features
+                .iter()
+                .map(|f| VcpCapability {
+                    code: f.feature_code,
+                    values: slice(f.values, f.value_ct),
+                })
+                .collect(),
+        };
+        unsafe { ddca_free_parsed_capabilities(parsed.as_ptr()) };
+        Ok(out)
+    }
+}
+
+/// Uses ddcutil to interact with the monitors
+///
+/// The display list is probed on the first call to [`DDCDriverTrait::probe`] and display handles
+/// are opened lazily and kept open until [`DDCDriverTrait::redetect`]. With a [`DetectionCache`]
+/// the probe is skipped while the cached monitors are still connected, and the displays are
+/// opened by their i2c bus.
+pub struct LinuxDDC {
+    unsupported: bool,
+    cache: Option<PathBuf>,
+    cached: OnceCell<Option<DetectionCache>>,
+    /// Set by [`DDCDriverTrait::redetect`], the cache was checked against older displays
+    redetected: Cell<bool>,
+    list: RefCell<Option<DisplayList>>,
+    handles: Handles<Display>,
+}
+
+impl LinuxDDC {
+    /// Create a new driver, `unsupported` also includes displays that do not support DDC/CI
+    pub fn new(unsupported: bool) -> Self {
+        Self {
+            unsupported,
+            cache: None,
+            cached: OnceCell::new(),
+            redetected: Cell::new(false),
+            list: RefCell::new(None),
+            handles: Handles::new(),
+        }
+    }
+
+    /// Keep the displays found by a full probe in the [`DetectionCache`] at `path`
+    pub fn with_cache(mut self, path: impl Into<PathBuf>) -> Self {
+        self.cache = Some(path.into());
+        self
+    }
+
+    fn list(&self) -> Result<DisplayList> {
+        if let Some(list) = &*self.list.borrow() {
+            return Ok(list.clone());
+        }
+        let list = DisplayList::probe(self.unsupported)?;
+        *self.list.borrow_mut() = Some(list.clone());
+        Ok(list)
+    }
+
+    /// The cached displays, if they are still the ones connected
+    fn cached(&self) -> Option<&DetectionCache> {
+        if self.redetected.get() {
+            return None;
+        }
+        self.cached
+            .get_or_init(|| {
+                let path = self.cache.as_deref()?;
+                let cache = DetectionCache::load(path)?;
+                if cache.is_valid(Path::new(SYSFS_DRM)) {
+                    tracing::info!("Using the cached displays from {}", path.display());
+                    Some(cache)
+                } else {
+                    tracing::info!("The connected displays changed, probing again");
+                    None
+                }
+            })
+            .as_ref()
+    }
+
+    /// Write the cache after a full probe
+    ///
+    /// Nothing is cached if a display can't be found again in sysfs by its connector and bus.
+    fn save_cache(&self, list: &DisplayList) {
+        let Some(path) = &self.cache else {
+            return;
+        };
+        let mut cache = DetectionCache::default();
+        for info in list.iter() {
+            let identity = info.identity();
+            let IOPath::I2C(bus) = identity.io_path else {
+                return;
+            };
+            if identity.drm.is_empty() {
+                return;
+            }
+            cache.displays.push(CachedDisplay {
+                edid: edid_hash(info.edid_bytes()),
+                bus,
+                identity,
+            });
+        }
+        cache.ignore_uncached(Path::new(SYSFS_DRM));
+        if let Err(e) = cache.save(path) {
+            tracing::warn!("Unable to write the cache {}: {:?}", path.display(), e);
+        }
+    }
+
+    fn with_display<T>(
+        &self,
+        display: &DisplayIdent,
+        f: impl FnOnce(&Display) -> Result<T>,
+    ) -> Result<T> {
+        self.handles
+            .with(display.io_path, || self.open(display), |handle| f(handle))
+    }
+
+    /// Let libddcutil find the single monitor `selector` matches by its EDID or serial number,
+    /// without reading the identity of every display
+    ///
+    /// The display is opened right away through the display ref libddcutil found, unless it's
+    /// already open. Its index is its position in the display list, which only holds the refs
+    /// libddcutil detected while finding it. `None` if the selector doesn't lend itself to it or
+    /// libddcutil doesn't find the monitor, the displays are then matched one by one.
+    fn find(&self, selector: &DisplaySelector) -> Result<Option<(DisplayIdent, DisplayIdentity)>> {
+        let Some(did) = DisplayIdentifier::new(selector)? else {
+            return Ok(None);
+        };
+        let dref = match did.display_ref() {
+            Ok(dref) => dref,
+            Err(e) => {
+                tracing::debug!("libddcutil didn't find the display: {:?}", e);
+                return Ok(None);
+            }
+        };
+        let info = DisplayInfo::from_ref(dref)?;
+        if !info.ddc_capable() {
+            return Ok(None);
+        }
+        let Some(index) = self.list()?.position(dref) else {
+            return Ok(None);
+        };
+        let display = DisplayIdent {
+            index,
+            io_path: info.io_path(),
+        };
+        let identity = info.identity();
+        if !selector.matches(&display, &identity) {
+            return Ok(None);
+        }
+        let open = || {
+            tracing::info!("Opening display: {} ({})", identity.model, identity.io_path);
+            info.open()
+        };
+        self.handles.with(display.io_path, open, |_| Ok(()))?;
+        Ok(Some((display, identity)))
+    }
+
+    fn open(&self, display: &DisplayIdent) -> Result<Display> {
+        let Some(cache) = self.cached() else {
+            let list = self.list()?;
+            let info = list.get(display.index)?;
+            tracing::info!("Opening display: {} ({})", info.model(), info.io_path());
+            return info.open();
+        };
+        let cached = cache
+            .displays
+            .get(display.index)
+            .ok_or_else(|| DDCError::new(DdcutilErrorKind::OutOfRange))?;
+        tracing::info!(
+            "Opening display: {} (bus {})",
+            cached.identity.model,
+            cached.bus
+        );
+        Display::open_bus(cached.bus).or_else(|e| {
+            // The displays handed out keep their cached index, only this one is found again by
+            // its EDID in a full probe, which also replaces the cache for the next commands
+            tracing::info!("Unable to open the cached display, probing again: {:?}", e);
+            if let Some(path) = &self.cache {
+                let _ = std::fs::remove_file(path);
+            }
+            let list = self.list()?;
+            self.save_cache(&list);
+            let info = list
+                .iter()
+                .find(|info| edid_hash(info.edid_bytes()) == cached.edid)
+                .ok_or(e)?;
+            info.open()
+        })
+    }
+}
+
+impl Default for LinuxDDC {
+    fn default() -> Self {
+        Self::new(true)
+    }
+}
+
+impl DDCDriverTrait for LinuxDDC {
+    fn probe(&self) -> Result<Vec<DisplayIdent>> {
+        if let Some(cache) = self.cached() {
+            return Ok(cache
+                .displays
+                .iter()
+                .enumerate()
+                .map(|(index, display)| DisplayIdent {
+                    index,
+                    io_path: display.identity.io_path,
+                })
+                .collect());
+        }
+        let list = self.list()?;
+        self.save_cache(&list);
+        Ok((0..list.len())
+            .filter_map(|index| {
+                list.get(index)
+                    .inspect_err(|e| {
+                        tracing::error!("Error getting display info at index {}: {:?}", index, e)
+                    })
+                    .ok()
+                    .map(|info| DisplayIdent {
+                        index,
+                        io_path: info.io_path(),
+                    })
+            })
+            .collect())
+    }
+
+    fn identify(&self, display: &DisplayIdent) -> Result<DisplayIdentity> {
+        match self.cached() {
+            Some(cache) => cache
+                .displays
+                .get(display.index)
+                .map(|cached| cached.identity.clone())
+                .ok_or_else(|| DDCError::new(DdcutilErrorKind::OutOfRange)),
+            None => Ok(self.list()?.get(display.index)?.identity()),
+        }
+    }
+
+    fn get_vcp(&self, display: &DisplayIdent, vcp: u8) -> Result<VCPFeature> {
+        self.with_display(display, |d| d.get_vcp(vcp))
+    }
+
+    fn set_vcp(&self, display: &DisplayIdent, vcp: u8, value: u16) -> Result<()> {
+        self.with_display(display, |d| d.set_vcp(vcp, value))
+    }
+
+    fn capabilities(&self, display: &DisplayIdent) -> Result<String> {
+        self.with_display(display, |d| d.capabilities_string())
+    }
+
+    fn select(&self, selector: &DisplaySelector) -> Result<Vec<(DisplayIdent, DisplayIdentity)>> {
+        // The cached identities are matched without talking to the monitors anyway
+        if self.cached().is_none() {
+            if let Some(found) = self.find(selector)? {
+                return Ok(vec![found]);
+            }
+        }
+        selector.probe(self)
+    }
+
+    fn watch(&self) -> Result<Watch> {
+        watch_displays()
+    }
+
+    fn redetect(&self) -> Result<()> {
+        // Close the displays before libddcutil frees their refs
+        self.handles.clear();
+        self.list.borrow_mut().take();
+        self.redetected.set(true);
+        LibDDCUtilError::from_rc(unsafe { ddca_redetect_displays() })
+    }
+}
+
+/// The senders of every running [`Watch`] by id
+type Watchers = Vec<(u64, Sender<DisplayEvent>)>;
+
+/// libddcutil's callback can't carry any state, so the watchers are global
+static WATCHERS: Mutex<Watchers> = Mutex::new(Vec::new());
+/// Held while starting or stopping the watch of libddcutil
+static WATCH: Mutex<()> = Mutex::new(());
+static NEXT_WATCHER: AtomicU64 = AtomicU64::new(0);
+
+fn lock<T>(mutex: &'static Mutex<T>) -> MutexGuard<'static, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl DisplayEvent {
+    fn from_ddca(event: &DDCA_Display_Status_Event) -> Option<Self> {
+        #[allow(non_upper_case_globals)]
+        let kind = match event.event_type {
+            DDCA_Display_Event_Type_DDCA_EVENT_DISPLAY_CONNECTED => DisplayEventKind::Connected,
+            DDCA_Display_Event_Type_DDCA_EVENT_DISPLAY_DISCONNECTED => {
+                DisplayEventKind::Disconnected
+            }
+            DDCA_Display_Event_Type_DDCA_EVENT_DPMS_ASLEEP => DisplayEventKind::Asleep,
+            DDCA_Display_Event_Type_DDCA_EVENT_DPMS_AWAKE => DisplayEventKind::Awake,
+            other => {
+                tracing::debug!("Ignoring display event of type {}", other);
+                return None;
+            }
+        };
+        Some(Self {
+            kind,
+            drm: lossy(&event.connector_name),
+            io_path: event.io_path.into(),
+        })
+    }
+}
+
+/// Called by libddcutil on its watch thread
+unsafe extern "C" fn display_status_callback(event: DDCA_Display_Status_Event) {
+    let Some(event) = DisplayEvent::from_ddca(&event) else {
+        return;
+    };
+    tracing::debug!("Display event: {:?}", event);
+    lock(&WATCHERS).retain(|(_, sender)| sender.send(event.clone()).is_ok());
+}
+
+/// Watch for hotplug and DPMS events with `ddca_start_watch_displays`
+///
+/// libddcutil only runs a single watch per process, it's started with the first [`Watch`] and
+/// stopped when the last one is dropped.
+pub fn watch_displays() -> Result<Watch> {
+    let _watch = lock(&WATCH);
+    let mut watchers = lock(&WATCHERS);
+    if watchers.is_empty() {
+        LibDDCUtilError::from_rc(unsafe {
+            ddca_register_display_status_callback(Some(display_status_callback))
+        })?;
+        let classes = DDCA_Display_Event_Class_DDCA_EVENT_CLASS_DPMS
+            | DDCA_Display_Event_Class_DDCA_EVENT_CLASS_DISPLAY_CONNECTION;
+        let rc = unsafe { ddca_start_watch_displays(classes) };
+        if rc != 0 {
+            unsafe { ddca_unregister_display_status_callback(Some(display_status_callback)) };
+            LibDDCUtilError::from_rc(rc)?;
+        }
+        tracing::info!("Started watching the displays");
+    }
+    let id = NEXT_WATCHER.fetch_add(1, Ordering::Relaxed);
+    let (sender, events) = mpsc::channel();
+    watchers.push((id, sender));
+    Ok(Watch::new(events).on_stop(move || {
+        let _watch = lock(&WATCH);
+        // The callback takes the lock of the senders, so it's released before waiting for the
+        // watch thread to stop
+        let last = {
+            let mut watchers = lock(&WATCHERS);
+            watchers.retain(|(watcher, _)| *watcher != id);
+            watchers.is_empty()
+        };
+        if last {
+            tracing::info!("Stopping the display watch");
+            unsafe {
+                ddca_stop_watch_displays(true);
+                ddca_unregister_display_status_callback(Some(display_status_callback));
+            }
+        }
+    }))
+}
+
 pub fn version() -> semver::Version {
     let version = unsafe { ddca_ddcutil_version() };
     semver::Version::new(
@@ -0,0 +1,52 @@
+/// The parts of a base EDID block that are used to identify a monitor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edid {
+    /// Three letter PNP manufacturer id (eg. `DEL`)
+    pub manufacturer: String,
+    pub product_code: u16,
+    pub serial_number: u32,
+    pub year: u16,
+    /// Display product name descriptor (`0xFC`)
+    pub model: Option<String>,
+    /// Display product serial number descriptor (`0xFF`)
+    pub serial: Option<String>,
+}
+
+impl Edid {
+    pub const HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+    pub const LEN: usize = 128;
+
+    /// Parse a base EDID block, returns `None` if the header or checksum is invalid
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let bytes = bytes.get(..Self::LEN)?;
+        if bytes[..8] != Self::HEADER || bytes.iter().fold(0u8, |a, b| a.wrapping_add(*b)) != 0 {
+            return None;
+        }
+        let mfg = u16::from_be_bytes([bytes[8], bytes[9]]);
+        let manufacturer = [10, 5, 0]
+            .iter()
+            .map(|shift| (((mfg >> shift) & 0x1f) as u8 + b'A' - 1) as char)
+            .collect();
+        let descriptor = |tag: u8| {
+            bytes[54..126]
+                .chunks_exact(18)
+                .find(|d| d[..3] == [0, 0, 0] && d[3] == tag)
+                .map(|d| {
+                    String::from_utf8_lossy(&d[5..])
+                        .split('\n')
+                        .next()
+                        .unwrap_or_default()
+                        .trim_end()
+                        .to_string()
+                })
+        };
+        Some(Self {
+            manufacturer,
+            product_code: u16::from_le_bytes([bytes[10], bytes[11]]),
+            serial_number: u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]),
+            year: bytes[17] as u16 + 1990,
+            model: descriptor(0xfc),
+            serial: descriptor(0xff),
+        })
+    }
+}
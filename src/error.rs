@@ -1,6 +1,7 @@
-use crate::ascii::AsAscii;
 use core::fmt::Display;
-use ddcutil_sys::bindings::{ddca_rc_desc, ddca_rc_name, DDCA_Status};
+use ddcutil_sys::bindings::{
+    DDCA_Status, DDCRC_DETERMINED_UNSUPPORTED, DDCRC_REPORTED_UNSUPPORTED,
+};
 use error_stack::Report;
 use thiserror::Error;
 
@@ -8,7 +9,6 @@ pub type Result<T, E = DDCError> = core::result::Result<T, E>;
 
 #[derive(Debug)]
 pub struct DDCError {
-    #[allow(dead_code)]
     kind: Report<DdcutilErrorKind>,
 }
 impl DDCError {
@@ -18,6 +18,37 @@ impl DDCError {
             kind: Report::from(no_displays),
         }
     }
+
+    pub fn kind(&self) -> &DdcutilErrorKind {
+        self.kind.current_context()
+    }
+
+    /// Whether the monitor or the backend doesn't support what was asked
+    pub fn is_unsupported(&self) -> bool {
+        match self.kind() {
+            DdcutilErrorKind::Unsupported => true,
+            DdcutilErrorKind::LibDDCUtilError(e) => matches!(
+                e.status(),
+                DDCRC_REPORTED_UNSUPPORTED | DDCRC_DETERMINED_UNSUPPORTED
+            ),
+            _ => false,
+        }
+    }
+}
+
+impl From<std::io::Error> for DDCError {
+    #[track_caller]
+    fn from(err: std::io::Error) -> Self {
+        DDCError::new(err.into())
+    }
+}
+
+#[cfg(feature = "dbus")]
+impl From<zbus::Error> for DDCError {
+    #[track_caller]
+    fn from(err: zbus::Error) -> Self {
+        DDCError::new(err.into())
+    }
 }
 
 #[derive(Debug, Error)]
@@ -30,6 +61,15 @@ pub enum DdcutilErrorKind {
     UnknownHandle,
     #[error("Out of Range")]
     OutOfRange,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Malformed capabilities string")]
+    MalformedCapabilities,
+    #[error("Not supported")]
+    Unsupported,
+    #[cfg(feature = "dbus")]
+    #[error(transparent)]
+    DBus(#[from] zbus::Error),
     #[error("Other error")]
     Other,
 }
@@ -38,7 +78,10 @@ pub enum DdcutilErrorKind {
 pub struct LibDDCUtilError(DDCA_Status);
 
 impl Display for LibDDCUtilError {
+    #[cfg(feature = "ddcutil")]
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use crate::ascii::AsAscii;
+        use ddcutil_sys::bindings::{ddca_rc_desc, ddca_rc_name};
         write!(
             f,
             "{}, {}, {:#x}",
@@ -47,6 +90,11 @@ impl Display for LibDDCUtilError {
             self.0
         )
     }
+
+    #[cfg(not(feature = "ddcutil"))]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DDC/CI error {}", self.0)
+    }
 }
 
 impl From<DDCA_Status> for LibDDCUtilError {
@@ -57,6 +105,11 @@ impl From<DDCA_Status> for LibDDCUtilError {
 }
 
 impl LibDDCUtilError {
+    /// The raw `DDCRC_*` status code
+    pub fn status(&self) -> DDCA_Status {
+        self.0
+    }
+
     #[track_caller]
     pub fn from_rc(status: DDCA_Status) -> Result<()> {
         if status == 0 {
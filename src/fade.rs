@@ -0,0 +1,218 @@
+use crate::*;
+use core::time::Duration;
+use std::path::PathBuf;
+use std::time::Instant;
+
+/// How the brightness progresses over the duration of a [`Fade`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Easing {
+    #[default]
+    Linear,
+    /// Start slow and speed up
+    EaseIn,
+    /// Start fast and slow down
+    EaseOut,
+    /// Slow at both ends
+    EaseInOut,
+}
+
+impl Easing {
+    /// Map the fraction `t` (0 to 1) of the elapsed time to the fraction of the change
+    pub fn apply(self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => t,
+            Self::EaseIn => t * t,
+            Self::EaseOut => t * (2.0 - t),
+            Self::EaseInOut if t < 0.5 => 2.0 * t * t,
+            Self::EaseInOut => 1.0 - 2.0 * (1.0 - t) * (1.0 - t),
+        }
+    }
+}
+
+/// A gradual change of the brightness, like xbacklight's `-time` and `-steps`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fade {
+    pub time: Duration,
+    pub steps: u32,
+    pub easing: Easing,
+}
+
+/// The brightness change of a single display during a [`Fade`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FadeTarget {
+    pub display: DisplayIdent,
+    pub from: u16,
+    pub to: u16,
+}
+
+impl Default for Fade {
+    /// Change the brightness at once
+    fn default() -> Self {
+        Self::new(Duration::ZERO, 1)
+    }
+}
+
+impl Fade {
+    pub fn new(time: Duration, steps: u32) -> Self {
+        Self {
+            time,
+            steps: steps.max(1),
+            easing: Easing::default(),
+        }
+    }
+
+    pub fn with_easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    /// Whether the fade is a single write of the final value
+    pub fn is_instant(&self) -> bool {
+        self.time.is_zero() || self.steps <= 1
+    }
+
+    /// The brightness after `step` of [`Fade::steps`] steps
+    pub fn value(&self, from: u16, to: u16, step: u32) -> u16 {
+        let t = self.easing.apply(step as f64 / self.steps as f64);
+        let value = from as f64 + (to as f64 - from as f64) * t;
+        value.round() as u16
+    }
+
+    /// When `step` is due, relative to the start of the fade
+    fn due(&self, step: u32) -> Duration {
+        self.time.mul_f64(step as f64 / self.steps as f64)
+    }
+
+    /// The last step that should have been written `elapsed` after the start of the fade
+    fn step_at(&self, elapsed: Duration) -> u32 {
+        let step = elapsed.as_secs_f64() / self.time.as_secs_f64() * self.steps as f64;
+        (step.floor() as u32).min(self.steps)
+    }
+
+    /// Fade every target from its current to its final brightness
+    ///
+    /// The displays fade over the same time but aren't written in parallel: they are stepped in
+    /// turns on the calling thread, as the drivers can't be shared between threads. A write
+    /// blocks the other displays, so a slow monitor holds back the others by at most one of its
+    /// writes at a time, and they skip the steps that came due meanwhile. Steps are also skipped
+    /// when a display takes longer to write a value than the time between two steps, so every
+    /// display still finishes on time. A display stops fading as soon as `cancelled` returns true
+    /// for it.
+    pub fn run<D: DDCDriverTrait + ?Sized>(
+        &self,
+        driver: &D,
+        targets: &[FadeTarget],
+        cancelled: impl Fn(&DisplayIdent) -> bool,
+    ) -> Result<()> {
+        if self.is_instant() {
+            for target in targets {
+                driver.backlight_set(&target.display, target.to)?;
+            }
+            return Ok(());
+        }
+
+        struct Progress {
+            /// The last step that was written
+            step: u32,
+            /// How long the last write to the display took
+            latency: Duration,
+        }
+        let start = Instant::now();
+        let mut progress = targets
+            .iter()
+            .map(|_| Progress {
+                step: 0,
+                latency: Duration::ZERO,
+            })
+            .collect::<Vec<_>>();
+        while let Some(i) = (0..targets.len())
+            .filter(|&i| progress[i].step < self.steps)
+            .min_by_key(|&i| self.due(progress[i].step + 1))
+        {
+            let (target, state) = (&targets[i], &mut progress[i]);
+            let due = start + self.due(state.step + 1);
+            if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                std::thread::sleep(wait);
+            }
+            if cancelled(&target.display) {
+                tracing::info!("Fade of {} cancelled", target.display.io_path);
+                state.step = self.steps;
+                continue;
+            }
+            // Catch up with the steps that should be on the screen by the time the write lands
+            let step = self
+                .step_at(start.elapsed() + state.latency)
+                .max(state.step + 1);
+            let (previous, value) = (
+                self.value(target.from, target.to, state.step),
+                self.value(target.from, target.to, step),
+            );
+            if value != previous || step == self.steps {
+                let write = Instant::now();
+                driver.backlight_set(&target.display, value)?;
+                state.latency = write.elapsed();
+                tracing::trace!(
+                    "Fade step {}/{} of {}: {} (took {:?})",
+                    step,
+                    self.steps,
+                    target.display.io_path,
+                    value,
+                    state.latency
+                );
+            }
+            state.step = step;
+        }
+        Ok(())
+    }
+}
+
+/// Tracks which command last changed the brightness of each display
+///
+/// Every command claims the displays it's about to change by writing a token unique to itself in
+/// a file per display under `$XDG_RUNTIME_DIR/ddcbacklight`. A fade still running in an older
+/// command notices that the claim changed hands and stops.
+#[derive(Debug, Clone)]
+pub struct Claims {
+    dir: PathBuf,
+    token: String,
+}
+
+impl Claims {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let since_epoch = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        Self {
+            dir: dir.into(),
+            token: format!("{}-{}", std::process::id(), since_epoch.as_nanos()),
+        }
+    }
+
+    /// Claims kept in `$XDG_RUNTIME_DIR/ddcbacklight`, or in a directory of the current user in
+    /// the temporary directory
+    pub fn runtime() -> Self {
+        Self::new(runtime_dir())
+    }
+
+    fn path(&self, io_path: &IOPath) -> PathBuf {
+        self.dir.join(match io_path {
+            IOPath::I2C(bus) => format!("i2c-{}", bus),
+            IOPath::Usb(dev) => format!("hiddev{}", dev),
+        })
+    }
+
+    /// Take over the display, cancelling the fades of older commands
+    pub fn claim(&self, io_path: &IOPath) -> Result<()> {
+        private_dir(&self.dir)?;
+        std::fs::write(self.path(io_path), &self.token)?;
+        Ok(())
+    }
+
+    /// Whether no newer command claimed the display since [`Claims::claim`]
+    ///
+    /// A missing or unreadable claim doesn't cancel anything.
+    pub fn is_claimed(&self, io_path: &IOPath) -> bool {
+        std::fs::read_to_string(self.path(io_path)).map_or(true, |token| token == self.token)
+    }
+}
@@ -0,0 +1,47 @@
+use crate::*;
+use core::cell::RefCell;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+/// The open handles of a driver by display, each display is only opened once
+///
+/// libddcutil refuses to open a display that is already open (`DDCRC_ALREADY_OPEN`), so a
+/// handle is kept until [`Handles::clear`] once opened.
+#[derive(Debug)]
+pub struct Handles<H> {
+    handles: RefCell<HashMap<IOPath, H>>,
+}
+
+impl<H> Default for Handles<H> {
+    fn default() -> Self {
+        Self {
+            handles: RefCell::new(HashMap::new()),
+        }
+    }
+}
+
+impl<H> Handles<H> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call `f` with the handle of the display at `io_path`, opened with `open` if it isn't yet
+    pub fn with<T>(
+        &self,
+        io_path: IOPath,
+        open: impl FnOnce() -> Result<H>,
+        f: impl FnOnce(&mut H) -> Result<T>,
+    ) -> Result<T> {
+        let mut handles = self.handles.borrow_mut();
+        let handle = match handles.entry(io_path) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(open()?),
+        };
+        f(handle)
+    }
+
+    /// Close every handle
+    pub fn clear(&self) {
+        self.handles.borrow_mut().clear();
+    }
+}
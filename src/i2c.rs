@@ -0,0 +1,424 @@
+use crate::error::*;
+use crate::*;
+use core::cell::{OnceCell, RefCell};
+use core::time::Duration;
+use ddcutil_sys::bindings::{
+    DDCRC_DDC_DATA, DDCRC_NULL_RESPONSE, DDCRC_REPORTED_UNSUPPORTED, DDCRC_RETRIES,
+};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::os::fd::AsRawFd;
+use std::path::PathBuf;
+
+/// 7 bit i2c address of the DDC/CI channel of the monitor
+pub const DDC_ADDRESS: u16 = 0x37;
+/// 7 bit i2c address of the EDID eeprom
+pub const EDID_ADDRESS: u16 = 0x50;
+
+/// Source address of the host in requests
+const HOST: u8 = 0x51;
+/// Destination address used for the checksum of requests (0x37 << 1)
+const DISPLAY: u8 = 0x6e;
+/// Destination address used for the checksum of replies
+const VIRTUAL_HOST: u8 = 0x50;
+
+const GET_VCP_REQUEST: u8 = 0x01;
+const GET_VCP_REPLY: u8 = 0x02;
+const SET_VCP_REQUEST: u8 = 0x03;
+const CAPABILITIES_REQUEST: u8 = 0xf3;
+const CAPABILITIES_REPLY: u8 = 0xe3;
+/// Largest capabilities fragment a monitor will send
+const FRAGMENT_LEN: usize = 32;
+/// Longest capabilities string read before giving up on a monitor that never ends it, 64
+/// fragments like ddcutil
+const CAPABILITIES_LEN: usize = 64 * FRAGMENT_LEN;
+const RETRIES: usize = 3;
+
+/// `I2C_SLAVE` from linux/i2c-dev.h
+const I2C_SLAVE: u64 = 0x0703;
+
+/// A device that is able to do plain i2c reads and writes
+///
+/// This is implemented for [`std::fs::File`] opened on a `/dev/i2c-*` node, and can be
+/// implemented by tests to simulate a monitor.
+pub trait I2cDevice {
+    /// Set the address the following reads and writes go to
+    fn set_address(&mut self, address: u16) -> std::io::Result<()>;
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<()>;
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<()>;
+}
+
+impl I2cDevice for std::fs::File {
+    fn set_address(&mut self, address: u16) -> std::io::Result<()> {
+        let rc = unsafe { libc::ioctl(self.as_raw_fd(), I2C_SLAVE as _, address as libc::c_ulong) };
+        if rc < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<()> {
+        self.read_exact(buf)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        self.write_all(buf)
+    }
+}
+
+/// Delays the host has to wait after each kind of request (DDC/CI 1.1 section 4)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Delays {
+    pub get_vcp: Duration,
+    pub set_vcp: Duration,
+    pub capabilities: Duration,
+}
+
+impl Default for Delays {
+    fn default() -> Self {
+        Self {
+            get_vcp: Duration::from_millis(40),
+            set_vcp: Duration::from_millis(50),
+            capabilities: Duration::from_millis(50),
+        }
+    }
+}
+
+impl Delays {
+    /// Scale all the delays, similar to ddcutil's `--sleep-multiplier`
+    pub fn scaled(self, multiplier: f64) -> Self {
+        Self {
+            get_vcp: self.get_vcp.mul_f64(multiplier),
+            set_vcp: self.set_vcp.mul_f64(multiplier),
+            capabilities: self.capabilities.mul_f64(multiplier),
+        }
+    }
+}
+
+/// The DDC/CI protocol spoken over an [`I2cDevice`]
+#[derive(Debug)]
+pub struct DDCCI<D> {
+    device: D,
+    delays: Delays,
+}
+
+fn ddc_error(status: i32) -> DDCError {
+    DDCError::new(DdcutilErrorKind::LibDDCUtilError(status.into()))
+}
+
+fn checksum(init: u8, bytes: &[u8]) -> u8 {
+    bytes.iter().fold(init, |acc, b| acc ^ b)
+}
+
+impl<D: I2cDevice> DDCCI<D> {
+    pub fn new(device: D) -> Self {
+        Self {
+            device,
+            delays: Delays::default(),
+        }
+    }
+
+    pub fn with_delays(mut self, delays: Delays) -> Self {
+        self.delays = delays;
+        self
+    }
+
+    pub fn into_inner(self) -> D {
+        self.device
+    }
+
+    /// Read the base EDID block
+    pub fn edid(&mut self) -> Result<[u8; Edid::LEN]> {
+        let mut edid = [0; Edid::LEN];
+        self.device.set_address(EDID_ADDRESS)?;
+        self.device.write(&[0])?;
+        self.device.read(&mut edid)?;
+        Ok(edid)
+    }
+
+    fn send(&mut self, payload: &[u8]) -> Result<()> {
+        let mut packet = Vec::with_capacity(payload.len() + 3);
+        packet.push(HOST);
+        packet.push(0x80 | payload.len() as u8);
+        packet.extend_from_slice(payload);
+        packet.push(checksum(DISPLAY, &packet));
+        tracing::trace!("DDC/CI write {:02x?}", packet);
+        self.device.set_address(DDC_ADDRESS)?;
+        self.device.write(&packet)?;
+        Ok(())
+    }
+
+    /// Read a reply of at most `len` payload bytes and return the validated payload
+    fn receive(&mut self, len: usize) -> Result<Vec<u8>> {
+        let mut packet = vec![0; len + 3];
+        self.device.read(&mut packet)?;
+        tracing::trace!("DDC/CI read {:02x?}", packet);
+        let payload_len = (packet[1] & 0x7f) as usize;
+        if payload_len == 0 {
+            return Err(ddc_error(DDCRC_NULL_RESPONSE));
+        }
+        if packet[0] != DISPLAY || packet[1] & 0x80 == 0 || payload_len > len {
+            return Err(ddc_error(DDCRC_DDC_DATA));
+        }
+        if checksum(VIRTUAL_HOST, &packet[..payload_len + 2]) != packet[payload_len + 2] {
+            return Err(ddc_error(DDCRC_DDC_DATA));
+        }
+        packet.truncate(payload_len + 2);
+        packet.drain(..2);
+        Ok(packet)
+    }
+
+    /// Run `f` until it succeeds, retrying on transient protocol errors
+    fn retry<T>(&mut self, mut f: impl FnMut(&mut Self) -> Result<T>) -> Result<T> {
+        for attempt in 1..=RETRIES {
+            match f(self) {
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        DdcutilErrorKind::LibDDCUtilError(s)
+                            if [DDCRC_DDC_DATA, DDCRC_NULL_RESPONSE].contains(&s.status())
+                    ) =>
+                {
+                    tracing::debug!("DDC/CI attempt {} failed, retrying", attempt);
+                }
+                out => return out,
+            }
+        }
+        Err(ddc_error(DDCRC_RETRIES))
+    }
+
+    pub fn get_vcp(&mut self, code: u8) -> Result<VCPFeature> {
+        self.retry(|ddc| {
+            ddc.send(&[GET_VCP_REQUEST, code])?;
+            std::thread::sleep(ddc.delays.get_vcp);
+            let reply = ddc.receive(8)?;
+            match reply.as_slice() {
+                [GET_VCP_REPLY, 0, c, _, mh, ml, sh, sl] if *c == code => {
+                    Ok(VCPFeature::from_bytes(code, *mh, *ml, *sh, *sl))
+                }
+                [GET_VCP_REPLY, 1, ..] => Err(ddc_error(DDCRC_REPORTED_UNSUPPORTED)),
+                _ => Err(ddc_error(DDCRC_DDC_DATA)),
+            }
+        })
+    }
+
+    pub fn set_vcp(&mut self, code: u8, value: u16) -> Result<()> {
+        let [hi_byte, lo_byte] = value.to_be_bytes();
+        self.send(&[SET_VCP_REQUEST, code, hi_byte, lo_byte])?;
+        std::thread::sleep(self.delays.set_vcp);
+        Ok(())
+    }
+
+    /// Read the capabilities string fragment by fragment
+    pub fn capabilities(&mut self) -> Result<String> {
+        let mut out = Vec::new();
+        loop {
+            let [hi_offset, lo_offset] = (out.len() as u16).to_be_bytes();
+            let fragment = self.retry(|ddc| {
+                ddc.send(&[CAPABILITIES_REQUEST, hi_offset, lo_offset])?;
+                std::thread::sleep(ddc.delays.capabilities);
+                let reply = ddc.receive(FRAGMENT_LEN + 3)?;
+                match reply.as_slice() {
+                    [CAPABILITIES_REPLY, hi, lo, data @ ..]
+                        if [*hi, *lo] == [hi_offset, lo_offset] =>
+                    {
+                        Ok(data.to_vec())
+                    }
+                    _ => Err(ddc_error(DDCRC_DDC_DATA)),
+                }
+            })?;
+            if fragment.is_empty() {
+                break;
+            }
+            out.extend(fragment);
+            if out.len() > CAPABILITIES_LEN {
+                tracing::debug!(
+                    "The capabilities string is longer than {} bytes",
+                    CAPABILITIES_LEN
+                );
+                return Err(ddc_error(DDCRC_DDC_DATA));
+            }
+        }
+        while out.last() == Some(&0) {
+            out.pop();
+        }
+        Ok(String::from_utf8_lossy(&out).into_owned())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct I2cDisplay {
+    bus: i32,
+    identity: DisplayIdentity,
+}
+
+/// Talks DDC/CI to the monitors directly over `/dev/i2c-*` without libddcutil
+///
+/// Every i2c bus of a drm connector (its `ddc` link or DP-AUX channel) that answers with a valid
+/// EDID is considered a display.
+#[derive(Debug)]
+pub struct I2cDDC {
+    dev: PathBuf,
+    sysfs: PathBuf,
+    delays: Delays,
+    displays: OnceCell<Vec<I2cDisplay>>,
+    handles: Handles<DDCCI<std::fs::File>>,
+    /// The capabilities read so far by bus, reading them takes dozens of DDC/CI round trips
+    capabilities: RefCell<HashMap<i32, String>>,
+}
+
+impl Default for I2cDDC {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl I2cDDC {
+    pub fn new() -> Self {
+        Self {
+            dev: PathBuf::from("/dev"),
+            sysfs: PathBuf::from("/sys/class/drm"),
+            delays: Delays::default(),
+            displays: OnceCell::new(),
+            handles: Handles::new(),
+            capabilities: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Open `i2c-*` nodes in `dev` and look for drm connectors in `sysfs` instead of the defaults
+    pub fn with_paths(mut self, dev: impl Into<PathBuf>, sysfs: impl Into<PathBuf>) -> Self {
+        self.dev = dev.into();
+        self.sysfs = sysfs.into();
+        self
+    }
+
+    pub fn with_delays(mut self, delays: Delays) -> Self {
+        self.delays = delays;
+        self
+    }
+
+    fn open(&self, bus: i32) -> Result<DDCCI<std::fs::File>> {
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(self.dev.join(format!("i2c-{}", bus)))?;
+        Ok(DDCCI::new(file).with_delays(self.delays))
+    }
+
+    /// Map i2c bus numbers to drm connectors using the `ddc` links in sysfs
+    fn connectors(&self) -> HashMap<i32, String> {
+        let mut out = HashMap::new();
+        let Ok(entries) = std::fs::read_dir(&self.sysfs) else {
+            return out;
+        };
+        for connector in entries.flatten() {
+            let name = connector.file_name().to_string_lossy().into_owned();
+            let ddc = std::fs::read_link(connector.path().join("ddc"))
+                .ok()
+                .and_then(|link| link.file_name().map(|f| f.to_string_lossy().into_owned()));
+            let aux = std::fs::read_dir(connector.path())
+                .into_iter()
+                .flatten()
+                .flatten()
+                .map(|e| e.file_name().to_string_lossy().into_owned());
+            for bus in ddc.into_iter().chain(aux).filter_map(|f| bus_number(&f)) {
+                out.insert(bus, name.clone());
+            }
+        }
+        out
+    }
+
+    fn displays(&self) -> Result<&[I2cDisplay]> {
+        if let Some(displays) = self.displays.get() {
+            return Ok(displays);
+        }
+        // Only the buses of drm connectors, writing to the other adapters (SMBus, SPD eeproms,
+        // sensors) isn't safe
+        let connectors = self.connectors();
+        let mut buses = connectors.keys().copied().collect::<Vec<_>>();
+        buses.sort_unstable();
+        let mut displays = Vec::new();
+        for bus in buses {
+            let edid = self.open(bus).and_then(|mut ddc| ddc.edid());
+            let Some(edid) = edid.ok().as_ref().and_then(|e| Edid::parse(e)) else {
+                tracing::debug!("No EDID on {}", IOPath::I2C(bus));
+                continue;
+            };
+            tracing::info!("Found display on {}: {:?}", IOPath::I2C(bus), edid.model);
+            displays.push(I2cDisplay {
+                bus,
+                identity: DisplayIdentity {
+                    model: edid.model.unwrap_or_default(),
+                    drm: connectors.get(&bus).cloned().unwrap_or_default(),
+                    io_path: IOPath::I2C(bus),
+                    manufacturer: edid.manufacturer,
+                    serial: edid.serial.unwrap_or_default(),
+                    product_code: edid.product_code,
+                    year: Some(edid.year),
+                    // Not known without talking DDC/CI to the monitor
+                    mccs_version: None,
+                    ddc: true,
+                },
+            });
+        }
+        Ok(self.displays.get_or_init(|| displays))
+    }
+
+    fn display(&self, display: &DisplayIdent) -> Result<&I2cDisplay> {
+        self.displays()?
+            .get(display.index)
+            .ok_or_else(|| DDCError::new(DdcutilErrorKind::OutOfRange))
+    }
+
+    fn with_display<T>(
+        &self,
+        display: &DisplayIdent,
+        f: impl FnOnce(&mut DDCCI<std::fs::File>) -> Result<T>,
+    ) -> Result<T> {
+        let bus = self.display(display)?.bus;
+        self.handles.with(IOPath::I2C(bus), || self.open(bus), f)
+    }
+}
+
+fn bus_number(name: &str) -> Option<i32> {
+    name.strip_prefix("i2c-")?.parse().ok()
+}
+
+impl DDCDriverTrait for I2cDDC {
+    fn probe(&self) -> Result<Vec<DisplayIdent>> {
+        Ok(self
+            .displays()?
+            .iter()
+            .enumerate()
+            .map(|(index, display)| DisplayIdent {
+                index,
+                io_path: display.identity.io_path,
+            })
+            .collect())
+    }
+
+    fn identify(&self, display: &DisplayIdent) -> Result<DisplayIdentity> {
+        Ok(self.display(display)?.identity.clone())
+    }
+
+    fn get_vcp(&self, display: &DisplayIdent, vcp: u8) -> Result<VCPFeature> {
+        self.with_display(display, |ddc| ddc.get_vcp(vcp))
+    }
+
+    fn set_vcp(&self, display: &DisplayIdent, vcp: u8, value: u16) -> Result<()> {
+        self.with_display(display, |ddc| ddc.set_vcp(vcp, value))
+    }
+
+    /// Read once per display, the features it supports are checked by every command
+    fn capabilities(&self, display: &DisplayIdent) -> Result<String> {
+        let bus = self.display(display)?.bus;
+        if let Some(capabilities) = self.capabilities.borrow().get(&bus) {
+            return Ok(capabilities.clone());
+        }
+        let capabilities = self.with_display(display, |ddc| ddc.capabilities())?;
+        self.capabilities
+            .borrow_mut()
+            .insert(bus, capabilities.clone());
+        Ok(capabilities)
+    }
+}
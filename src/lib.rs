@@ -1,9 +1,93 @@
+#[cfg(feature = "ddcutil")]
 mod ascii;
+mod cache;
+mod capabilities;
+pub mod cli;
+mod config;
+mod daemon;
+#[cfg(feature = "dbus")]
+pub mod dbus;
+#[cfg(feature = "ddcutil")]
 mod ddc;
+mod edid;
 mod error;
-use core::marker::PhantomData;
+mod fade;
+mod handles;
+#[cfg(feature = "i2c")]
+mod i2c;
+mod mock;
+mod output;
+mod profile;
+mod restore;
+mod selector;
+mod vcp;
+mod watch;
+pub use cache::*;
+pub use capabilities::*;
+pub use config::*;
+pub use daemon::*;
+#[cfg(feature = "ddcutil")]
 pub use ddc::*;
-use error::Result;
+pub use edid::*;
+pub use error::{DDCError, DdcutilErrorKind, LibDDCUtilError, Result};
+pub use fade::*;
+pub use handles::*;
+#[cfg(feature = "i2c")]
+pub use i2c::*;
+pub use mock::*;
+pub use output::*;
+pub use profile::*;
+pub use restore::*;
+pub use selector::*;
+pub use vcp::*;
+pub use watch::*;
+
+/// `$XDG_RUNTIME_DIR/ddcbacklight`, or `ddcbacklight-<uid>` in the temporary directory
+pub(crate) fn runtime_dir() -> std::path::PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => std::path::Path::new(&dir).join("ddcbacklight"),
+        None => std::env::temp_dir().join(format!("ddcbacklight-{}", uid())),
+    }
+}
+
+pub(crate) fn uid() -> u32 {
+    unsafe { libc::getuid() }
+}
+
+/// Create `dir` only accessible to the current user, or check that an existing one can't be
+/// written by anyone else
+///
+/// Another user could otherwise put symlinks or their own daemon socket in there.
+pub(crate) fn private_dir(dir: &std::path::Path) -> Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+    if let Some(parent) = dir.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        Err(e) if e.kind() != std::io::ErrorKind::AlreadyExists => return Err(e.into()),
+        _ => {}
+    }
+    let metadata = std::fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() || metadata.uid() != uid() || metadata.mode() & 0o022 != 0 {
+        tracing::error!(
+            "Refusing to use {}, it isn't a directory only the current user can write to",
+            dir.display()
+        );
+        return Err(std::io::Error::from(std::io::ErrorKind::PermissionDenied).into());
+    }
+    Ok(())
+}
+
+/// `$XDG_CONFIG_HOME/ddcbacklight`, falling back to `~/.config`
+pub(crate) fn config_dir() -> Option<std::path::PathBuf> {
+    let dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(std::path::PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| std::path::Path::new(&home).join(".config"))
+        })?;
+    Some(dir.join("ddcbacklight"))
+}
 
 /// The main entry point for the library.
 /// This contains the main struct that will be used to interact with all the monitors
@@ -11,29 +95,269 @@ pub struct DDC {}
 
 /// The ddc driver that will be used to interact with the monitors
 pub enum DDCDriver {
-    Windows(WindowsDDC),
+    #[cfg(feature = "ddcutil")]
     Linux(LinuxDDC),
+    #[cfg(feature = "i2c")]
+    I2C(I2cDDC),
+    Mock(MockDDC),
 }
 
-/// Uses windows native functions to interact
-pub struct WindowsDDC {}
+impl DDCDriverTrait for DDCDriver {
+    fn probe(&self) -> Result<Vec<DisplayIdent>> {
+        match self {
+            #[cfg(feature = "ddcutil")]
+            Self::Linux(driver) => driver.probe(),
+            #[cfg(feature = "i2c")]
+            Self::I2C(driver) => driver.probe(),
+            Self::Mock(driver) => driver.probe(),
+        }
+    }
+
+    fn identify(&self, display: &DisplayIdent) -> Result<DisplayIdentity> {
+        match self {
+            #[cfg(feature = "ddcutil")]
+            Self::Linux(driver) => driver.identify(display),
+            #[cfg(feature = "i2c")]
+            Self::I2C(driver) => driver.identify(display),
+            Self::Mock(driver) => driver.identify(display),
+        }
+    }
+
+    fn get_vcp(&self, display: &DisplayIdent, vcp: u8) -> Result<VCPFeature> {
+        match self {
+            #[cfg(feature = "ddcutil")]
+            Self::Linux(driver) => driver.get_vcp(display, vcp),
+            #[cfg(feature = "i2c")]
+            Self::I2C(driver) => driver.get_vcp(display, vcp),
+            Self::Mock(driver) => driver.get_vcp(display, vcp),
+        }
+    }
+
+    fn set_vcp(&self, display: &DisplayIdent, vcp: u8, value: u16) -> Result<()> {
+        match self {
+            #[cfg(feature = "ddcutil")]
+            Self::Linux(driver) => driver.set_vcp(display, vcp, value),
+            #[cfg(feature = "i2c")]
+            Self::I2C(driver) => driver.set_vcp(display, vcp, value),
+            Self::Mock(driver) => driver.set_vcp(display, vcp, value),
+        }
+    }
 
-/// Uses ddcutil to interact with the monitors
-pub struct LinuxDDC {}
+    fn capabilities(&self, display: &DisplayIdent) -> Result<String> {
+        match self {
+            #[cfg(feature = "ddcutil")]
+            Self::Linux(driver) => driver.capabilities(display),
+            #[cfg(feature = "i2c")]
+            Self::I2C(driver) => driver.capabilities(display),
+            Self::Mock(driver) => driver.capabilities(display),
+        }
+    }
+
+    fn select(&self, selector: &DisplaySelector) -> Result<Vec<(DisplayIdent, DisplayIdentity)>> {
+        match self {
+            #[cfg(feature = "ddcutil")]
+            Self::Linux(driver) => driver.select(selector),
+            #[cfg(feature = "i2c")]
+            Self::I2C(driver) => driver.select(selector),
+            Self::Mock(driver) => driver.select(selector),
+        }
+    }
+
+    fn watch(&self) -> Result<Watch> {
+        match self {
+            #[cfg(feature = "ddcutil")]
+            Self::Linux(driver) => driver.watch(),
+            #[cfg(feature = "i2c")]
+            Self::I2C(driver) => driver.watch(),
+            Self::Mock(driver) => driver.watch(),
+        }
+    }
+
+    fn redetect(&self) -> Result<()> {
+        match self {
+            #[cfg(feature = "ddcutil")]
+            Self::Linux(driver) => driver.redetect(),
+            #[cfg(feature = "i2c")]
+            Self::I2C(driver) => driver.redetect(),
+            Self::Mock(driver) => driver.redetect(),
+        }
+    }
+}
 
 /// A display identifier
-/// Placeholder
+/// This is handed out by [`DDCDriverTrait::probe`] and is only valid for the driver that created it
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct DisplayIdent {
-    __marker: PhantomData<()>,
+    /// Index of the display in the driver's display list
+    pub index: usize,
+    pub io_path: IOPath,
+}
+
+/// Identifying information about a display
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct DisplayIdentity {
+    pub model: String,
+    /// The drm connector the display is attached to (eg. `card1-DP-1`)
+    pub drm: String,
+    pub io_path: IOPath,
+    /// Three letter PNP manufacturer id (eg. `DEL`)
+    pub manufacturer: String,
+    pub serial: String,
+    pub product_code: u16,
+    /// Year of manufacture from the EDID
+    pub year: Option<u16>,
+    /// MCCS version reported by the monitor (eg. `2.1`)
+    pub mccs_version: Option<String>,
+    /// Whether the display can be controlled over DDC/CI
+    pub ddc: bool,
+}
+
+impl DisplayIdentity {
+    /// The drm connector without the card prefix (eg. `DP-1`)
+    pub fn connector(&self) -> &str {
+        self.drm.split_once('-').map(|s| s.1).unwrap_or(&self.drm)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum IOPath {
+    I2C(i32),
+    Usb(i32),
 }
 
-/// Placeholder
-pub struct VCPFeature {
-    __marker: PhantomData<()>,
+impl serde::Serialize for IOPath {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
 }
 
+impl<'de> serde::Deserialize<'de> for IOPath {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let path = String::deserialize(deserializer)?;
+        let number = |prefix| path.strip_prefix(prefix)?.parse().ok();
+        number("/dev/i2c-")
+            .map(Self::I2C)
+            .or_else(|| number("/dev/hiddev").map(Self::Usb))
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid io path {:?}", path)))
+    }
+}
+
+impl core::fmt::Display for IOPath {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::I2C(bus) => write!(f, "/dev/i2c-{}", bus),
+            Self::Usb(dev) => write!(f, "/dev/hiddev{}", dev),
+        }
+    }
+}
+
+/// A backend that is able to talk DDC/CI to the monitors
 pub trait DDCDriverTrait {
-    fn probe(&self) -> Result<DisplayList>;
+    /// List all the displays reachable through this driver
+    fn probe(&self) -> Result<Vec<DisplayIdent>>;
+    /// Read the identifying information of a display
+    fn identify(&self, display: &DisplayIdent) -> Result<DisplayIdentity>;
+    /// Read a non-table VCP feature
     fn get_vcp(&self, display: &DisplayIdent, vcp: u8) -> Result<VCPFeature>;
-    fn set_vcp(&self, display: &DisplayIdent, vcp: u8) -> Result<()>;
+    /// Write a non-table VCP feature
+    fn set_vcp(&self, display: &DisplayIdent, vcp: u8, value: u16) -> Result<()>;
+    /// Read the raw MCCS capabilities string
+    fn capabilities(&self, display: &DisplayIdent) -> Result<String>;
+
+    fn backlight_get(&self, display: &DisplayIdent) -> Result<Backlight> {
+        Ok(self.get_vcp(display, VCPFeature::BACKLIGHT)?.into())
+    }
+
+    /// Write the raw brightness
+    fn backlight_set(&self, display: &DisplayIdent, value: u16) -> Result<()> {
+        self.set_vcp(display, VCPFeature::BACKLIGHT, value)
+    }
+
+    fn contrast_get(&self, display: &DisplayIdent) -> Result<Contrast> {
+        Ok(self.get_vcp(display, VCPFeature::CONTRAST)?.into())
+    }
+
+    /// Write the raw contrast
+    fn contrast_set(&self, display: &DisplayIdent, value: u16) -> Result<()> {
+        self.set_vcp(display, VCPFeature::CONTRAST, value)
+    }
+
+    /// Fail with [`DdcutilErrorKind::Unsupported`] if the capabilities of the display lack `vcp`
+    ///
+    /// Displays whose capabilities can't be read or parsed are assumed to support it, many
+    /// report broken ones.
+    fn require(&self, display: &DisplayIdent, vcp: u8) -> Result<()> {
+        Capabilities::require(
+            self.capabilities(display)
+                .and_then(|raw| Capabilities::parse(&raw)),
+            vcp,
+        )
+    }
+
+    fn volume_get(&self, display: &DisplayIdent) -> Result<Volume> {
+        self.require(display, VCPFeature::VOLUME)?;
+        Ok(self.get_vcp(display, VCPFeature::VOLUME)?.into())
+    }
+
+    /// Write the raw volume
+    fn volume_set(&self, display: &DisplayIdent, value: u16) -> Result<()> {
+        self.require(display, VCPFeature::VOLUME)?;
+        self.set_vcp(display, VCPFeature::VOLUME, value)
+    }
+
+    fn muted(&self, display: &DisplayIdent) -> Result<bool> {
+        self.require(display, VCPFeature::MUTE)?;
+        Ok(self.get_vcp(display, VCPFeature::MUTE)?.sl == 1)
+    }
+
+    fn set_muted(&self, display: &DisplayIdent, muted: bool) -> Result<()> {
+        self.require(display, VCPFeature::MUTE)?;
+        self.set_vcp(display, VCPFeature::MUTE, if muted { 1 } else { 2 })
+    }
+
+    /// Read the power mode from VCP 0xD6, or 0xE1 on monitors only supporting that
+    fn power(&self, display: &DisplayIdent) -> Result<PowerMode> {
+        let code = PowerMode::feature(
+            self.capabilities(display)
+                .and_then(|raw| Capabilities::parse(&raw)),
+        )?;
+        PowerMode::try_from(&self.get_vcp(display, code)?)
+    }
+
+    /// Switch the power mode
+    ///
+    /// Monitors only supporting VCP 0xE1 can only be turned on or off, the ones listing their
+    /// VCP 0xD6 values can only be switched to those.
+    fn set_power(&self, display: &DisplayIdent, mode: PowerMode) -> Result<()> {
+        let (code, value) = mode.setting(
+            self.capabilities(display)
+                .and_then(|raw| Capabilities::parse(&raw)),
+        )?;
+        self.set_vcp(display, code, value)
+    }
+
+    fn input(&self, display: &DisplayIdent) -> Result<Input> {
+        Ok(Input::from(self.get_vcp(display, VCPFeature::INPUT)?.sl))
+    }
+
+    fn set_input(&self, display: &DisplayIdent, input: Input) -> Result<()> {
+        self.set_vcp(display, VCPFeature::INPUT, u8::try_from(input)?.into())
+    }
+
+    /// The displays matching `selector`, with their identity
+    fn select(&self, selector: &DisplaySelector) -> Result<Vec<(DisplayIdent, DisplayIdentity)>> {
+        selector.probe(self)
+    }
+
+    /// Watch for displays being connected, disconnected, put to sleep or woken up
+    fn watch(&self) -> Result<Watch> {
+        tracing::error!("Watching the displays isn't supported by this backend");
+        Err(DDCError::new(DdcutilErrorKind::Unsupported))
+    }
+
+    /// Forget the displays found so far, the next [`DDCDriverTrait::probe`] finds the ones
+    /// connected now
+    fn redetect(&self) -> Result<()> {
+        Ok(())
+    }
 }
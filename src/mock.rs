@@ -0,0 +1,221 @@
+use crate::error::*;
+use crate::*;
+use core::cell::RefCell;
+use core::time::Duration;
+use ddcutil_sys::bindings::{DDCA_Status, DDCRC_REPORTED_UNSUPPORTED};
+use std::collections::{BTreeMap, HashMap};
+
+/// An in-memory driver that simulates a set of displays
+///
+/// Useful for exercising the library and the cli without a monitor or libddcutil.
+/// ```
+/// # use ddcbacklight::*;
+/// # use core::time::Duration;
+/// let driver = MockDDC::new()
+///     .with_display(MockDisplay::new("DELL U2720Q", "card1-DP-1", 4).with_vcp(0x10, 50, 100))
+///     .with_latency(Duration::from_millis(50));
+/// ```
+#[derive(Debug, Default)]
+pub struct MockDDC {
+    displays: Vec<MockDisplay>,
+    latency: Duration,
+    probe_failure: Option<DDCA_Status>,
+    events: Vec<DisplayEvent>,
+}
+
+/// A single simulated display
+#[derive(Debug, Clone)]
+pub struct MockDisplay {
+    identity: DisplayIdentity,
+    capabilities: String,
+    vcp: RefCell<BTreeMap<u8, (u16, u16)>>,
+    writes: RefCell<Vec<(u8, u16)>>,
+    latency: Duration,
+    failures: HashMap<MockOp, DDCA_Status>,
+}
+
+/// An operation on a [`MockDisplay`] that can be made to fail
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MockOp {
+    Identify,
+    GetVcp(u8),
+    SetVcp(u8),
+    Capabilities,
+}
+
+impl MockDDC {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_display(mut self, display: MockDisplay) -> Self {
+        self.displays.push(display);
+        self
+    }
+
+    /// Delay every DDC operation by `latency`
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Make [`DDCDriverTrait::probe`] fail with the `DDCRC_*` `status`
+    pub fn with_probe_failure(mut self, status: DDCA_Status) -> Self {
+        self.probe_failure = Some(status);
+        self
+    }
+
+    /// Report `events` from [`DDCDriverTrait::watch`], which ends after the last one
+    pub fn with_events(mut self, events: impl IntoIterator<Item = DisplayEvent>) -> Self {
+        self.events.extend(events);
+        self
+    }
+
+    /// Every `(code, value)` written to the display at `index`, oldest first
+    pub fn writes(&self, index: usize) -> Vec<(u8, u16)> {
+        self.displays
+            .get(index)
+            .map(|display| display.writes.borrow().clone())
+            .unwrap_or_default()
+    }
+
+    /// The current and max values of a feature on the display at `index`
+    pub fn vcp(&self, index: usize, code: u8) -> Option<(u16, u16)> {
+        self.displays.get(index)?.vcp.borrow().get(&code).copied()
+    }
+
+    fn display(&self, display: &DisplayIdent) -> Result<&MockDisplay> {
+        self.displays
+            .get(display.index)
+            .ok_or_else(|| DDCError::new(DdcutilErrorKind::OutOfRange))
+    }
+
+    fn simulate(&self, display: &DisplayIdent, op: MockOp) -> Result<&MockDisplay> {
+        let display = self.display(display)?;
+        let latency = self.latency + display.latency;
+        if !latency.is_zero() {
+            std::thread::sleep(latency);
+        }
+        match display.failures.get(&op) {
+            Some(&status) => Err(fail(status)),
+            None => Ok(display),
+        }
+    }
+}
+
+impl MockDisplay {
+    /// A display on i2c `bus` that supports no features
+    pub fn new(model: impl Into<String>, drm: impl Into<String>, bus: i32) -> Self {
+        Self {
+            identity: DisplayIdentity {
+                model: model.into(),
+                drm: drm.into(),
+                io_path: IOPath::I2C(bus),
+                manufacturer: String::new(),
+                serial: String::new(),
+                product_code: 0,
+                year: None,
+                mccs_version: None,
+                ddc: true,
+            },
+            capabilities: String::new(),
+            vcp: RefCell::new(BTreeMap::new()),
+            writes: RefCell::new(Vec::new()),
+            latency: Duration::ZERO,
+            failures: HashMap::new(),
+        }
+    }
+
+    /// Add a feature with its `current` and `max` values
+    pub fn with_vcp(self, code: u8, current: u16, max: u16) -> Self {
+        self.vcp.borrow_mut().insert(code, (current, max));
+        self
+    }
+
+    /// Change the identity (manufacturer, serial, ...) of the display
+    pub fn with_identity(mut self, f: impl FnOnce(&mut DisplayIdentity)) -> Self {
+        f(&mut self.identity);
+        self
+    }
+
+    /// Delay every DDC operation on this display by `latency`, on top of the driver latency
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    pub fn with_capabilities(mut self, capabilities: impl Into<String>) -> Self {
+        self.capabilities = capabilities.into();
+        self
+    }
+
+    /// Make `op` fail with the `DDCRC_*` `status`
+    pub fn with_failure(mut self, op: MockOp, status: DDCA_Status) -> Self {
+        self.failures.insert(op, status);
+        self
+    }
+}
+
+fn fail(status: DDCA_Status) -> DDCError {
+    DDCError::new(DdcutilErrorKind::LibDDCUtilError(status.into()))
+}
+
+impl DDCDriverTrait for MockDDC {
+    fn probe(&self) -> Result<Vec<DisplayIdent>> {
+        if let Some(status) = self.probe_failure {
+            return Err(fail(status));
+        }
+        Ok(self
+            .displays
+            .iter()
+            .enumerate()
+            .map(|(index, display)| DisplayIdent {
+                index,
+                io_path: display.identity.io_path,
+            })
+            .collect())
+    }
+
+    fn identify(&self, display: &DisplayIdent) -> Result<DisplayIdentity> {
+        Ok(self.simulate(display, MockOp::Identify)?.identity.clone())
+    }
+
+    fn get_vcp(&self, display: &DisplayIdent, vcp: u8) -> Result<VCPFeature> {
+        let display = self.simulate(display, MockOp::GetVcp(vcp))?;
+        let (current, max) = display
+            .vcp
+            .borrow()
+            .get(&vcp)
+            .copied()
+            .ok_or_else(|| fail(DDCRC_REPORTED_UNSUPPORTED))?;
+        let [mh, ml] = max.to_be_bytes();
+        let [sh, sl] = current.to_be_bytes();
+        Ok(VCPFeature::from_bytes(vcp, mh, ml, sh, sl))
+    }
+
+    fn set_vcp(&self, display: &DisplayIdent, vcp: u8, value: u16) -> Result<()> {
+        let display = self.simulate(display, MockOp::SetVcp(vcp))?;
+        let mut table = display.vcp.borrow_mut();
+        let (current, _) = table
+            .get_mut(&vcp)
+            .ok_or_else(|| fail(DDCRC_REPORTED_UNSUPPORTED))?;
+        *current = value;
+        display.writes.borrow_mut().push((vcp, value));
+        Ok(())
+    }
+
+    fn capabilities(&self, display: &DisplayIdent) -> Result<String> {
+        Ok(self
+            .simulate(display, MockOp::Capabilities)?
+            .capabilities
+            .clone())
+    }
+
+    fn watch(&self) -> Result<Watch> {
+        let (sender, events) = std::sync::mpsc::channel();
+        for event in &self.events {
+            let _ = sender.send(event.clone());
+        }
+        Ok(Watch::new(events))
+    }
+}
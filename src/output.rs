@@ -0,0 +1,520 @@
+use crate::*;
+use colored::Colorize;
+use serde::Serialize;
+use std::io::Write;
+
+/// How the results of a command are printed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable colored lines
+    #[default]
+    Plain,
+    /// Aligned columns with a header
+    Table,
+    /// A json array with one object per display
+    Json,
+    /// Only the value, one line per display (like `xbacklight -get`)
+    Value,
+}
+
+/// Which command produced a [`Record`], only used to pick the plain format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordKind {
+    Detect,
+    Brightness,
+    Contrast,
+    Volume,
+    Mute,
+    Power,
+    Input,
+    SetInput,
+    Capabilities,
+    /// A feature saved to or applied from a profile
+    Profile,
+}
+
+/// The result of a command for a single display
+#[derive(Debug, Clone, Serialize)]
+pub struct Record {
+    #[serde(skip)]
+    pub kind: RecordKind,
+    /// Index of the display in the driver, only set by `detect`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<usize>,
+    #[serde(flatten)]
+    pub display: DisplayIdentity,
+    /// The i2c bus number, if the display is connected over i2c
+    pub bus: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub percent: Option<u16>,
+    /// The unit the brightness is printed in by the plain format
+    #[serde(skip)]
+    pub unit: BrightnessUnit,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input: Option<Input>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub muted: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub power: Option<PowerMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw_capabilities: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Capabilities>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub feature: Option<ProfileFeature>,
+    /// Why the feature couldn't be read or written
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Record {
+    fn new(kind: RecordKind, display: &DisplayIdentity) -> Self {
+        Self {
+            kind,
+            index: None,
+            display: display.clone(),
+            bus: match display.io_path {
+                IOPath::I2C(bus) => Some(bus),
+                IOPath::Usb(_) => None,
+            },
+            current: None,
+            max: None,
+            percent: None,
+            unit: BrightnessUnit::default(),
+            input: None,
+            muted: None,
+            power: None,
+            raw_capabilities: None,
+            capabilities: None,
+            feature: None,
+            error: None,
+        }
+    }
+
+    pub fn detect(index: usize, display: &DisplayIdentity) -> Self {
+        Self {
+            index: Some(index),
+            ..Self::new(RecordKind::Detect, display)
+        }
+    }
+
+    pub fn brightness(
+        display: &DisplayIdentity,
+        backlight: &Backlight,
+        unit: BrightnessUnit,
+    ) -> Self {
+        Self {
+            current: Some(backlight.current),
+            max: Some(backlight.max),
+            percent: Some(backlight.percent()),
+            unit,
+            ..Self::new(RecordKind::Brightness, display)
+        }
+    }
+
+    pub fn contrast(display: &DisplayIdentity, contrast: &Contrast, unit: BrightnessUnit) -> Self {
+        Self {
+            kind: RecordKind::Contrast,
+            feature: Some(ProfileFeature::Contrast),
+            ..Self::brightness(display, contrast, unit)
+        }
+    }
+
+    pub fn volume(display: &DisplayIdentity, volume: &Volume, unit: BrightnessUnit) -> Self {
+        Self {
+            kind: RecordKind::Volume,
+            feature: Some(ProfileFeature::Volume),
+            ..Self::brightness(display, volume, unit)
+        }
+    }
+
+    pub fn mute(display: &DisplayIdentity, muted: bool) -> Self {
+        Self {
+            muted: Some(muted),
+            ..Self::new(RecordKind::Mute, display)
+        }
+    }
+
+    pub fn power(display: &DisplayIdentity, power: PowerMode) -> Self {
+        Self {
+            power: Some(power),
+            ..Self::new(RecordKind::Power, display)
+        }
+    }
+
+    pub fn input(display: &DisplayIdentity, input: Input) -> Self {
+        Self {
+            input: Some(input),
+            ..Self::new(RecordKind::Input, display)
+        }
+    }
+
+    pub fn set_input(display: &DisplayIdentity, input: Input) -> Self {
+        Self {
+            kind: RecordKind::SetInput,
+            ..Self::input(display, input)
+        }
+    }
+
+    pub fn raw_capabilities(display: &DisplayIdentity, raw: String) -> Self {
+        Self {
+            raw_capabilities: Some(raw),
+            ..Self::new(RecordKind::Capabilities, display)
+        }
+    }
+
+    pub fn capabilities(display: &DisplayIdentity, capabilities: Capabilities) -> Self {
+        Self {
+            capabilities: Some(capabilities),
+            ..Self::new(RecordKind::Capabilities, display)
+        }
+    }
+
+    /// The raw `value` of a profile feature
+    pub fn profile(display: &DisplayIdentity, feature: ProfileFeature, value: u16) -> Self {
+        Self {
+            current: Some(value),
+            feature: Some(feature),
+            ..Self::new(RecordKind::Profile, display)
+        }
+    }
+
+    /// A profile feature that couldn't be read or written
+    pub fn profile_failure(
+        display: &DisplayIdentity,
+        feature: ProfileFeature,
+        error: &DDCError,
+    ) -> Self {
+        Self {
+            feature: Some(feature),
+            error: Some(error.kind().to_string()),
+            ..Self::new(RecordKind::Profile, display)
+        }
+    }
+}
+
+impl OutputFormat {
+    pub fn print(&self, records: &[Record], out: &mut impl Write) -> Result<()> {
+        match self {
+            Self::Plain => {
+                for record in records {
+                    print_plain(record, out)?;
+                }
+            }
+            Self::Table => print_table(records, out)?,
+            Self::Json => {
+                serde_json::to_writer_pretty(&mut *out, records).map_err(std::io::Error::from)?;
+                writeln!(out)?;
+            }
+            Self::Value => {
+                for record in records {
+                    print_value(record, out)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OutputFormat {
+    /// Print a single event of `watch` as soon as it arrives, json is printed one object per line
+    pub fn print_event(&self, event: &DisplayEvent, out: &mut impl Write) -> Result<()> {
+        match self {
+            Self::Json => {
+                serde_json::to_writer(&mut *out, event).map_err(std::io::Error::from)?;
+                writeln!(out)?;
+            }
+            Self::Value => writeln!(out, "{} {}", event.kind, event.connector())?,
+            Self::Plain | Self::Table => {
+                let kind = format!("{:<12}", event.kind);
+                let kind = match event.kind {
+                    DisplayEventKind::Connected
+                    | DisplayEventKind::Awake
+                    | DisplayEventKind::Resumed => kind.green(),
+                    DisplayEventKind::Disconnected => kind.red(),
+                    DisplayEventKind::Asleep => kind.yellow(),
+                };
+                writeln!(out, "{} {} ({})", kind, event.connector(), event.io_path)?
+            }
+        }
+        out.flush()?;
+        Ok(())
+    }
+}
+
+fn print_plain(record: &Record, out: &mut impl Write) -> Result<()> {
+    let display = &record.display;
+    match record.kind {
+        RecordKind::Detect => {
+            let optional = |value: Option<String>| value.unwrap_or_else(|| "unknown".into());
+            writeln!(
+                out,
+                "{} {}",
+                "Display".green(),
+                record.index.unwrap_or_default()
+            )?;
+            let path = match display.io_path {
+                IOPath::I2C(_) => "I2C bus",
+                IOPath::Usb(_) => "USB device",
+            };
+            writeln!(out, "   {:<18}{}", format!("{}:", path), display.io_path)?;
+            writeln!(out, "   {:<18}{}", "DRM connector:", display.drm)?;
+            writeln!(out, "   {:<18}{}", "Manufacturer:", display.manufacturer)?;
+            writeln!(out, "   {:<18}{}", "Model:", display.model)?;
+            writeln!(out, "   {:<18}{}", "Serial number:", display.serial)?;
+            writeln!(
+                out,
+                "   {:<18}{} ({:#06x})",
+                "Product code:", display.product_code, display.product_code
+            )?;
+            writeln!(
+                out,
+                "   {:<18}{}",
+                "Manufacture year:",
+                optional(display.year.map(|y| y.to_string()))
+            )?;
+            writeln!(
+                out,
+                "   {:<18}{}",
+                "MCCS version:",
+                optional(display.mccs_version.clone())
+            )?;
+            writeln!(
+                out,
+                "   {:<18}{}",
+                "DDC capable:",
+                if display.ddc { "yes" } else { "no" }
+            )?;
+        }
+        RecordKind::Brightness | RecordKind::Contrast | RecordKind::Volume => {
+            let (current, max) = match record.unit {
+                BrightnessUnit::Percent => (record.percent, Some(100)),
+                BrightnessUnit::Raw => (record.current, record.max),
+            };
+            writeln!(
+                out,
+                "{:<15}:({:^8}) {:>3}/{:>3}",
+                display.model.green(),
+                display.connector(),
+                current.unwrap_or_default(),
+                max.unwrap_or_default()
+            )?
+        }
+        RecordKind::Mute => writeln!(
+            out,
+            "{:<15}:({:^8}) {}",
+            display.model.green(),
+            display.connector(),
+            if record.muted == Some(true) {
+                "muted"
+            } else {
+                "unmuted"
+            }
+        )?,
+        RecordKind::Power => writeln!(
+            out,
+            "{:<15}:({:^8}) {}",
+            display.model.green(),
+            display.connector(),
+            record.power.map(|p| p.to_string()).unwrap_or_default()
+        )?,
+        RecordKind::Input => writeln!(
+            out,
+            "{:<15}: {:?} (Connected as {}): {}",
+            display.model.green(),
+            record.input.unwrap_or(Input::Raw(0)),
+            display.connector(),
+            display.io_path
+        )?,
+        RecordKind::Capabilities => {
+            writeln!(out, "{} ({}):", display.model.green(), display.connector())?;
+            if let Some(raw) = &record.raw_capabilities {
+                writeln!(out, "{}", raw)?;
+            }
+            if let Some(capabilities) = &record.capabilities {
+                print_capabilities(capabilities, out)?;
+            }
+        }
+        RecordKind::SetInput => writeln!(
+            out,
+            "{}: {:?}",
+            display.model.blue(),
+            record.input.unwrap_or(Input::Raw(0))
+        )?,
+        RecordKind::Profile => {
+            let feature = record.feature.map(|f| f.to_string()).unwrap_or_default();
+            let value = match &record.error {
+                Some(error) => format!("{}: {}", "failed".red(), error),
+                None => record.current.unwrap_or_default().to_string(),
+            };
+            writeln!(
+                out,
+                "{:<15}:({:^8}) {:<12} {}",
+                display.model.green(),
+                display.connector(),
+                feature,
+                value
+            )?
+        }
+    }
+    Ok(())
+}
+
+fn print_value(record: &Record, out: &mut impl Write) -> Result<()> {
+    match record.kind {
+        RecordKind::Brightness | RecordKind::Contrast | RecordKind::Volume => {
+            let value = match record.unit {
+                BrightnessUnit::Percent => record.percent,
+                BrightnessUnit::Raw => record.current,
+            };
+            writeln!(out, "{}", value.unwrap_or_default())?
+        }
+        RecordKind::Mute => writeln!(
+            out,
+            "{}",
+            if record.muted == Some(true) {
+                "on"
+            } else {
+                "off"
+            }
+        )?,
+        RecordKind::Power => writeln!(
+            out,
+            "{}",
+            record.power.map(|p| p.to_string()).unwrap_or_default()
+        )?,
+        RecordKind::Input | RecordKind::SetInput => {
+            writeln!(out, "{}", record.input.unwrap_or(Input::Raw(0)))?
+        }
+        RecordKind::Profile => match record.current {
+            Some(value) => writeln!(out, "{}", value)?,
+            None => writeln!(out, "failed")?,
+        },
+        // Nothing is a single value here
+        RecordKind::Detect | RecordKind::Capabilities => print_plain(record, out)?,
+    }
+    Ok(())
+}
+
+fn print_capabilities(capabilities: &Capabilities, out: &mut impl Write) -> Result<()> {
+    let hex = |bytes: &[u8]| {
+        bytes
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let optional = |value: &Option<String>| value.clone().unwrap_or_else(|| "unknown".into());
+    writeln!(out, "   {:<14}{}", "Model:", optional(&capabilities.model))?;
+    writeln!(
+        out,
+        "   {:<14}{}",
+        "MCCS version:",
+        optional(&capabilities.mccs_version)
+    )?;
+    writeln!(out, "   {:<14}{}", "Commands:", hex(&capabilities.commands))?;
+    writeln!(out, "   VCP features:")?;
+    for feature in &capabilities.features {
+        if feature.values.is_empty() {
+            writeln!(out, "      {:02X}", feature.code)?;
+        } else {
+            writeln!(out, "      {:02X}: {}", feature.code, hex(&feature.values))?;
+        }
+    }
+    Ok(())
+}
+
+/// A table column, its header and how to get the cell from a record
+type Column = (&'static str, fn(&Record) -> Option<String>);
+
+const DETECT_COLUMNS: [Column; 10] = [
+    ("INDEX", |r| r.index.map(|i| i.to_string())),
+    ("IO PATH", |r| Some(r.display.io_path.to_string())),
+    ("CONNECTOR", |r| Some(r.display.connector().to_string())),
+    ("MFG", |r| Some(r.display.manufacturer.clone())),
+    ("MODEL", |r| Some(r.display.model.clone())),
+    ("SERIAL", |r| Some(r.display.serial.clone())),
+    ("PRODUCT", |r| {
+        Some(format!("{:#06x}", r.display.product_code))
+    }),
+    ("YEAR", |r| r.display.year.map(|y| y.to_string())),
+    ("MCCS", |r| r.display.mccs_version.clone()),
+    ("DDC", |r| {
+        Some(if r.display.ddc { "yes" } else { "no" }.into())
+    }),
+];
+
+fn print_table(records: &[Record], out: &mut impl Write) -> Result<()> {
+    let optional = |value: Option<String>| value.unwrap_or_else(|| "-".into());
+    let columns: [Column; 15] = [
+        ("MODEL", |r| Some(r.display.model.clone())),
+        ("CONNECTOR", |r| Some(r.display.connector().to_string())),
+        ("IO PATH", |r| Some(r.display.io_path.to_string())),
+        ("BUS", |r| r.bus.map(|b| b.to_string())),
+        ("FEATURE", |r| r.feature.map(|f| f.to_string())),
+        ("CURRENT", |r| r.current.map(|c| c.to_string())),
+        ("MAX", |r| r.max.map(|m| m.to_string())),
+        ("PERCENT", |r| r.percent.map(|p| format!("{}%", p))),
+        ("INPUT", |r| r.input.map(|i| i.to_string())),
+        ("MUTED", |r| {
+            r.muted.map(|m| if m { "yes" } else { "no" }.into())
+        }),
+        ("POWER", |r| r.power.map(|p| p.to_string())),
+        ("MCCS", |r| r.capabilities.as_ref()?.mccs_version.clone()),
+        ("VCP", |r| {
+            let capabilities = r.capabilities.as_ref()?;
+            let codes = capabilities
+                .features
+                .iter()
+                .map(|f| format!("{:02X}", f.code));
+            Some(codes.collect::<Vec<_>>().join(","))
+        }),
+        ("CAPABILITIES", |r| r.raw_capabilities.clone()),
+        ("ERROR", |r| r.error.clone()),
+    ];
+    let columns: &[Column] = if records.iter().all(|r| r.kind == RecordKind::Detect) {
+        &DETECT_COLUMNS
+    } else {
+        &columns
+    };
+    // Skip the columns that are empty for every record
+    let columns = columns
+        .iter()
+        .filter(|(_, value)| records.iter().any(|r| value(r).is_some()))
+        .collect::<Vec<_>>();
+    let rows = records
+        .iter()
+        .map(|r| {
+            columns
+                .iter()
+                .map(|(_, value)| optional(value(r)))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let widths = columns
+        .iter()
+        .enumerate()
+        .map(|(i, (name, _))| {
+            rows.iter()
+                .map(|row| row[i].len())
+                .chain([name.len()])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+    let header = columns.iter().map(|(name, _)| name.to_string());
+    for row in core::iter::once(header.collect::<Vec<_>>()).chain(rows) {
+        let line = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        writeln!(out, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
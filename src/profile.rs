@@ -0,0 +1,174 @@
+use crate::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// A feature kept in a [`Profile`]
+///
+/// Features are applied in this order: the color preset before the gains it resets, and the input
+/// last as the monitor may stop answering once it switched away.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    clap::ValueEnum,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProfileFeature {
+    Brightness,
+    Contrast,
+    ColorPreset,
+    RedGain,
+    GreenGain,
+    BlueGain,
+    Volume,
+    Input,
+}
+
+impl ProfileFeature {
+    pub const ALL: &'static [Self] = &[
+        Self::Brightness,
+        Self::Contrast,
+        Self::ColorPreset,
+        Self::RedGain,
+        Self::GreenGain,
+        Self::BlueGain,
+        Self::Volume,
+        Self::Input,
+    ];
+
+    /// The vcp code of the feature
+    pub fn code(self) -> u8 {
+        match self {
+            Self::Brightness => VCPFeature::BACKLIGHT,
+            Self::Contrast => VCPFeature::CONTRAST,
+            Self::ColorPreset => VCPFeature::COLOR_PRESET,
+            Self::RedGain => VCPFeature::RED_GAIN,
+            Self::GreenGain => VCPFeature::GREEN_GAIN,
+            Self::BlueGain => VCPFeature::BLUE_GAIN,
+            Self::Volume => VCPFeature::VOLUME,
+            Self::Input => VCPFeature::INPUT,
+        }
+    }
+
+    /// The value to save from what the monitor reported
+    ///
+    /// Only the low byte of the input is meaningful, some monitors put garbage in the high one.
+    pub fn value(self, feature: &VCPFeature) -> u16 {
+        match self {
+            Self::Input => feature.sl.into(),
+            _ => feature.current,
+        }
+    }
+}
+
+impl core::fmt::Display for ProfileFeature {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::Brightness => "brightness",
+            Self::Contrast => "contrast",
+            Self::ColorPreset => "color-preset",
+            Self::RedGain => "red-gain",
+            Self::GreenGain => "green-gain",
+            Self::BlueGain => "blue-gain",
+            Self::Volume => "volume",
+            Self::Input => "input",
+        })
+    }
+}
+
+/// A named snapshot of the raw feature values of the monitors, stored as TOML
+///
+/// ```toml
+/// [displays.DEL-a0f2-5KC2R83]
+/// model = "DELL U2720Q"
+/// brightness = 30
+/// input = 17
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    /// The values of each monitor, see [`Profile::key`]
+    #[serde(default)]
+    pub displays: BTreeMap<String, ProfileDisplay>,
+}
+
+/// The values of a single monitor in a [`Profile`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileDisplay {
+    /// Only there to tell the monitors apart when reading the file
+    #[serde(default)]
+    pub model: String,
+    #[serde(flatten)]
+    pub values: BTreeMap<ProfileFeature, u16>,
+}
+
+impl Profile {
+    /// `$XDG_CONFIG_HOME/ddcbacklight/profiles`, falling back to `~/.config`
+    pub fn dir() -> Option<PathBuf> {
+        Some(config_dir()?.join("profiles"))
+    }
+
+    /// The file of the profile `name` in `dir`
+    pub fn path(dir: &Path, name: &str) -> Result<PathBuf> {
+        if name.is_empty() || name.starts_with('.') || name.contains(std::path::is_separator) {
+            tracing::error!("{:?} isn't a valid profile name", name);
+            return Err(DDCError::new(DdcutilErrorKind::Other));
+        }
+        Ok(dir.join(format!("{}.toml", name)))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = std::fs::read_to_string(path).inspect_err(|e| {
+            tracing::error!("Unable to read the profile {}: {}", path.display(), e)
+        })?;
+        toml::from_str(&file).map_err(|e| {
+            tracing::error!("Invalid profile {}: {}", path.display(), e);
+            DDCError::from(std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = toml::to_string_pretty(self)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        std::fs::write(path, file)?;
+        Ok(())
+    }
+
+    /// The key of a monitor, the one of [`RestoreState::key`]
+    ///
+    /// Monitors without a serial number are told apart by their connector instead.
+    pub fn key(identity: &DisplayIdentity) -> String {
+        RestoreState::key(identity).unwrap_or_else(|| {
+            format!(
+                "{}-{:04x}-{}",
+                identity.manufacturer,
+                identity.product_code,
+                identity.connector()
+            )
+        })
+    }
+
+    /// The values saved for a monitor
+    pub fn values(&self, identity: &DisplayIdentity) -> Option<&BTreeMap<ProfileFeature, u16>> {
+        self.displays
+            .get(&Self::key(identity))
+            .map(|display| &display.values)
+    }
+
+    /// Save the `value` of `feature` for a monitor
+    pub fn set(&mut self, identity: &DisplayIdentity, feature: ProfileFeature, value: u16) {
+        let display = self.displays.entry(Self::key(identity)).or_default();
+        display.model.clone_from(&identity.model);
+        display.values.insert(feature, value);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// The features remembered for each monitor, in the order they are restored
+pub const RESTORED_FEATURES: [u8; 3] = [
+    VCPFeature::BACKLIGHT,
+    VCPFeature::CONTRAST,
+    VCPFeature::INPUT,
+];
+
+/// The values last set on each monitor, reapplied when it comes back
+///
+/// Monitors are keyed by the serial number in their EDID (see [`RestoreState::key`]), so the
+/// values follow a monitor from one connector or dock to the other.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RestoreState {
+    /// The raw value of each feature by vcp code, for each monitor
+    pub displays: BTreeMap<String, BTreeMap<u8, u16>>,
+}
+
+impl RestoreState {
+    /// `$XDG_STATE_HOME/ddcbacklight/restore.json`, falling back to `~/.local/state`
+    pub fn path() -> Option<PathBuf> {
+        let dir = std::env::var_os("XDG_STATE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("state"))
+            })?;
+        Some(dir.join("ddcbacklight").join("restore.json"))
+    }
+
+    /// Read the state, empty if it doesn't exist or can't be read
+    pub fn load(path: &Path) -> Self {
+        let Ok(file) = std::fs::read(path) else {
+            return Self::default();
+        };
+        serde_json::from_slice(&file)
+            .inspect_err(|e| tracing::warn!("Ignoring invalid state {}: {}", path.display(), e))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_vec_pretty(self).map_err(std::io::Error::from)?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// The key of a monitor: its manufacturer, product code and EDID serial number
+    ///
+    /// `None` for monitors without a serial number, they can't be told apart from another one of
+    /// the same model.
+    pub fn key(identity: &DisplayIdentity) -> Option<String> {
+        let serial = identity.serial.trim();
+        (!serial.is_empty()).then(|| {
+            format!(
+                "{}-{:04x}-{}",
+                identity.manufacturer, identity.product_code, serial
+            )
+        })
+    }
+
+    /// The remembered values of a monitor, by vcp code
+    pub fn values(&self, identity: &DisplayIdentity) -> Option<&BTreeMap<u8, u16>> {
+        self.displays.get(&Self::key(identity)?)
+    }
+
+    /// Remember that `code` was set to `value` on a monitor, only [`RESTORED_FEATURES`] are kept
+    pub fn set(&mut self, identity: &DisplayIdentity, code: u8, value: u16) {
+        if !RESTORED_FEATURES.contains(&code) {
+            return;
+        }
+        if let Some(key) = Self::key(identity) {
+            self.displays.entry(key).or_default().insert(code, value);
+        }
+    }
+
+    /// Update the state at `path` with the values a command just set
+    ///
+    /// Failing to write the state only logs a warning, the values were set after all.
+    pub fn remember(path: &Path, values: &[(&DisplayIdentity, u8, u16)]) {
+        let mut state = Self::load(path);
+        for &(identity, code, value) in values {
+            state.set(identity, code, value);
+        }
+        if let Err(e) = state.save(path) {
+            tracing::warn!("Unable to write the state {}: {:?}", path.display(), e);
+        }
+    }
+
+    /// Reapply the remembered values to the display at `io_path`
+    ///
+    /// Returns the `(code, value)` written, empty if nothing is remembered for the monitor.
+    pub fn restore<D: DDCDriverTrait + ?Sized>(
+        &self,
+        driver: &D,
+        io_path: IOPath,
+    ) -> Result<Vec<(u8, u16)>> {
+        let Some(display) = driver
+            .probe()?
+            .into_iter()
+            .find(|display| display.io_path == io_path)
+        else {
+            tracing::info!("{} isn't a known display", io_path);
+            return Ok(Vec::new());
+        };
+        let identity = driver.identify(&display)?;
+        let Some(values) = self.values(&identity) else {
+            return Ok(Vec::new());
+        };
+        let mut written = Vec::new();
+        for code in RESTORED_FEATURES {
+            if let Some(&value) = values.get(&code) {
+                tracing::info!("Restoring {:#04x} of {} to {}", code, identity.model, value);
+                driver.set_vcp(&display, code, value)?;
+                written.push((code, value));
+            }
+        }
+        Ok(written)
+    }
+}
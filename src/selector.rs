@@ -0,0 +1,199 @@
+use crate::error::*;
+use crate::*;
+
+/// Which displays a command applies to
+///
+/// Parsed from `key=value` terms, `,` between terms requires all of them, `|` any of them (`,`
+/// binds tighter) and a leading `!` negates a term:
+/// ```
+/// # use ddcbacklight::*;
+/// let selector: DisplaySelector = "mfg=DEL,!serial=5KC2R83|bus=5".parse().unwrap();
+/// assert_eq!(
+///     selector,
+///     DisplaySelector::Or(vec![
+///         DisplaySelector::And(vec![
+///             DisplaySelector::Manufacturer("DEL".into()),
+///             DisplaySelector::Not(Box::new(DisplaySelector::Serial("5KC2R83".into()))),
+///         ]),
+///         DisplaySelector::Bus(5),
+///     ])
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisplaySelector {
+    /// The model name (`model=`), case insensitive
+    Model(String),
+    /// The serial number in the EDID (`serial=`)
+    Serial(String),
+    /// Three letter PNP manufacturer id (`mfg=`), case insensitive
+    Manufacturer(String),
+    /// The product code in the EDID (`product=`), decimal or `0x` hex
+    ProductCode(u16),
+    /// The number of the display printed by `detect` (`display=`)
+    Display(usize),
+    /// The i2c bus (`bus=`)
+    Bus(i32),
+    /// The USB HID device (`hiddev=`, the N of `hiddevN`)
+    Hiddev(i32),
+    /// Part of the drm connector (`connector=`, eg. `DP-1`), case insensitive
+    Connector(String),
+    /// The monitor with this base EDID block (`edid=`, 256 hex digits)
+    Edid(Vec<u8>),
+    Not(Box<Self>),
+    /// Every selector matches, every display if empty
+    And(Vec<Self>),
+    /// Any selector matches, no display if empty
+    Or(Vec<Self>),
+}
+
+impl Default for DisplaySelector {
+    fn default() -> Self {
+        Self::And(Vec::new())
+    }
+}
+
+impl DisplaySelector {
+    /// Matches when every one of `selectors` does
+    pub fn all(mut selectors: Vec<Self>) -> Self {
+        match selectors.len() {
+            1 => selectors.remove(0),
+            _ => Self::And(selectors),
+        }
+    }
+
+    /// Matches when any of `selectors` does
+    pub fn any(mut selectors: Vec<Self>) -> Self {
+        match selectors.len() {
+            1 => selectors.remove(0),
+            _ => Self::Or(selectors),
+        }
+    }
+
+    pub fn matches(&self, display: &DisplayIdent, identity: &DisplayIdentity) -> bool {
+        let same = |a: &str, b: &str| a.trim().eq_ignore_ascii_case(b.trim());
+        match self {
+            Self::Model(model) => same(model, &identity.model),
+            Self::Serial(serial) => serial.trim() == identity.serial.trim(),
+            Self::Manufacturer(mfg) => same(mfg, &identity.manufacturer),
+            Self::ProductCode(code) => *code == identity.product_code,
+            Self::Display(index) => *index == display.index,
+            Self::Bus(bus) => identity.io_path == IOPath::I2C(*bus),
+            Self::Hiddev(dev) => identity.io_path == IOPath::Usb(*dev),
+            Self::Connector(connector) => identity
+                .drm
+                .to_lowercase()
+                .contains(&connector.to_lowercase()),
+            Self::Edid(bytes) => Edid::parse(bytes).is_some_and(|edid| {
+                same(&edid.manufacturer, &identity.manufacturer)
+                    && edid.product_code == identity.product_code
+                    && edid
+                        .serial
+                        .is_none_or(|serial| serial == identity.serial.trim())
+                    && edid.model.is_none_or(|model| same(&model, &identity.model))
+            }),
+            Self::Not(selector) => !selector.matches(display, identity),
+            Self::And(selectors) => selectors.iter().all(|s| s.matches(display, identity)),
+            Self::Or(selectors) => selectors.iter().any(|s| s.matches(display, identity)),
+        }
+    }
+
+    /// Probe `driver` and identify every display to find the ones matching
+    ///
+    /// What [`DDCDriverTrait::select`] does unless the driver knows a faster way.
+    pub fn probe<D: DDCDriverTrait + ?Sized>(
+        &self,
+        driver: &D,
+    ) -> Result<Vec<(DisplayIdent, DisplayIdentity)>> {
+        let mut out = Vec::new();
+        for display in driver.probe()? {
+            let identity = driver.identify(&display)?;
+            if self.matches(&display, &identity) {
+                out.push((display, identity));
+            }
+        }
+        Ok(out)
+    }
+
+    /// The model, manufacturer and serial number this selector requires
+    ///
+    /// `None` unless the selector is only made of those and includes the serial number, so it can
+    /// only match a single monitor.
+    pub fn model_serial(&self) -> Option<(Option<&str>, Option<&str>, &str)> {
+        let terms = match self {
+            Self::And(terms) => terms.as_slice(),
+            term => core::slice::from_ref(term),
+        };
+        let (mut mfg, mut model, mut serial) = (None, None, None);
+        for term in terms {
+            match term {
+                Self::Manufacturer(value) if mfg.is_none() => mfg = Some(value.as_str()),
+                Self::Model(value) if model.is_none() => model = Some(value.as_str()),
+                Self::Serial(value) if serial.is_none() => serial = Some(value.as_str()),
+                _ => return None,
+            }
+        }
+        Some((
+            mfg,
+            model,
+            serial.filter(|serial| !serial.trim().is_empty())?,
+        ))
+    }
+
+    fn term(term: &str) -> Result<Self> {
+        let term = term.trim();
+        if let Some(negated) = term.strip_prefix('!') {
+            return Ok(Self::Not(Box::new(Self::term(negated)?)));
+        }
+        let invalid = || DDCError::new(DdcutilErrorKind::Other);
+        let (key, value) = term.split_once('=').ok_or_else(invalid)?;
+        let value = value.trim();
+        let number = |value: &str| match value.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok(),
+            None => value.parse().ok(),
+        };
+        let number = || number(value).ok_or_else(invalid);
+        let out_of_range = |_| DDCError::new(DdcutilErrorKind::OutOfRange);
+        Ok(match key.trim() {
+            "model" => Self::Model(value.into()),
+            "serial" | "sn" => Self::Serial(value.into()),
+            "mfg" | "manufacturer" => Self::Manufacturer(value.into()),
+            "product" => Self::ProductCode(number()?.try_into().map_err(out_of_range)?),
+            "display" => Self::Display(number()?.try_into().map_err(out_of_range)?),
+            "bus" => Self::Bus(number()?.try_into().map_err(out_of_range)?),
+            "hiddev" => Self::Hiddev(number()?.try_into().map_err(out_of_range)?),
+            "connector" | "name" => Self::Connector(value.into()),
+            "edid" => {
+                let digits = value.as_bytes();
+                if digits.len() != 2 * Edid::LEN {
+                    return Err(invalid());
+                }
+                let edid = digits
+                    .chunks_exact(2)
+                    .map(|hex| {
+                        core::str::from_utf8(hex)
+                            .ok()
+                            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(invalid)?;
+                Self::Edid(edid)
+            }
+            _ => return Err(invalid()),
+        })
+    }
+}
+
+impl core::str::FromStr for DisplaySelector {
+    type Err = DDCError;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let any = s
+            .split('|')
+            .map(|all| {
+                Ok(Self::all(
+                    all.split(',').map(Self::term).collect::<Result<_>>()?,
+                ))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self::any(any))
+    }
+}
@@ -0,0 +1,514 @@
+use crate::error::*;
+use crate::Capabilities;
+
+/// A non-table VCP feature value
+///
+/// `current` and `max` are the big-endian combination of the raw `sh`/`sl` and `mh`/`ml` bytes.
+/// Features that encode something other than a continuous value (eg. the input source) should be
+/// interpreted from the raw bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VCPFeature {
+    pub code: u8,
+    pub current: u16,
+    pub max: u16,
+    pub mh: u8,
+    pub ml: u8,
+    pub sh: u8,
+    pub sl: u8,
+}
+
+impl VCPFeature {
+    pub const BACKLIGHT: u8 = 0x10;
+    pub const CONTRAST: u8 = 0x12;
+    pub const COLOR_PRESET: u8 = 0x14;
+    pub const RED_GAIN: u8 = 0x16;
+    pub const GREEN_GAIN: u8 = 0x18;
+    pub const BLUE_GAIN: u8 = 0x1a;
+    pub const INPUT: u8 = 0x60;
+    pub const VOLUME: u8 = 0x62;
+    /// The audio mute, 1 when muted and 2 when not
+    pub const MUTE: u8 = 0x8d;
+    /// See [`PowerMode`]
+    pub const POWER_MODE: u8 = 0xd6;
+    /// Manufacturer specific power switch some monitors have instead of 0xd6, 1 when on and 0
+    /// when off
+    pub const POWER_CONTROL: u8 = 0xe1;
+
+    pub fn from_bytes(code: u8, mh: u8, ml: u8, sh: u8, sl: u8) -> Self {
+        Self {
+            code,
+            current: u16::from_be_bytes([sh, sl]),
+            max: u16::from_be_bytes([mh, ml]),
+            mh,
+            ml,
+            sh,
+            sl,
+        }
+    }
+}
+
+/// The contrast, ranging from 0 to a max reported by the monitor
+pub type Contrast = Backlight;
+
+/// The speaker volume, ranging from 0 to a max reported by the monitor
+pub type Volume = Backlight;
+
+/// A continuous VCP value ranging from 0 to a max reported by the monitor
+///
+/// The setters of the drivers take raw values, [`Backlight::raw`] converts from a value in
+/// either [`BrightnessUnit`].
+#[derive(Debug)]
+pub struct Backlight {
+    pub current: u16,
+    pub max: u16,
+}
+
+/// The unit of the brightness values given to and printed by the cli
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum BrightnessUnit {
+    /// Percent of the max brightness reported by the monitor
+    #[default]
+    Percent,
+    /// The raw VCP 0x10 value, between 0 and the max reported by the monitor
+    Raw,
+}
+
+impl Backlight {
+    /// The current brightness in percent of the max, rounded to the nearest percent
+    pub fn percent(&self) -> u16 {
+        self.to_percent(self.current)
+    }
+
+    /// Convert a raw `value` to percent of the max, rounded to the nearest percent
+    pub fn to_percent(&self, value: u16) -> u16 {
+        match self.max {
+            0 => 0,
+            max => ((value as u32 * 200 + max as u32) / (2 * max as u32)) as u16,
+        }
+    }
+
+    /// The raw value closest to `percent` of the max
+    pub fn from_percent(&self, percent: u16) -> u16 {
+        ((percent as u32 * self.max as u32 * 2 + 100) / 200) as u16
+    }
+
+    /// The current brightness in `unit`
+    pub fn get(&self, unit: BrightnessUnit) -> u16 {
+        match unit {
+            BrightnessUnit::Percent => self.percent(),
+            BrightnessUnit::Raw => self.current,
+        }
+    }
+
+    /// The raw value for a brightness of `value` in `unit`
+    ///
+    /// Fails if `value` is above 100 percent or above the max raw value.
+    pub fn raw(&self, unit: BrightnessUnit, value: u16) -> Result<u16> {
+        let limit = match unit {
+            BrightnessUnit::Percent => 100,
+            BrightnessUnit::Raw => self.max,
+        };
+        if value > limit {
+            return Err(DDCError::new(DdcutilErrorKind::OutOfRange));
+        }
+        Ok(match unit {
+            BrightnessUnit::Percent => self.from_percent(value),
+            BrightnessUnit::Raw => value,
+        })
+    }
+
+    /// The raw value after changing the brightness by `delta` in `unit`, clamped to the valid range
+    ///
+    /// A non-zero change always moves the raw value by at least one, so small percent steps
+    /// still work on monitors with a max below 100.
+    pub fn step(&self, unit: BrightnessUnit, delta: i32) -> u16 {
+        let target = match unit {
+            BrightnessUnit::Percent => {
+                let percent = (self.percent() as i32 + delta).clamp(0, 100);
+                self.from_percent(percent as u16) as i32
+            }
+            BrightnessUnit::Raw => self.current as i32 + delta,
+        };
+        let target = match delta.signum() {
+            1 => target.max(self.current as i32 + 1),
+            -1 => target.min(self.current as i32 - 1),
+            _ => target,
+        };
+        target.clamp(0, self.max as i32) as u16
+    }
+}
+
+impl From<VCPFeature> for Backlight {
+    fn from(feature: VCPFeature) -> Self {
+        Self {
+            current: feature.current,
+            max: feature.max,
+        }
+    }
+}
+
+/// A brightness value, raw for plain numbers (`30`) and in percent with a `%` suffix (`50%`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BrightnessAmount {
+    pub value: u16,
+    pub unit: BrightnessUnit,
+}
+
+/// A brightness adjustment in the style of brightnessctl
+///
+/// Parsed from `50%` or `30` (set), `+10%` or `+5` (increase), `-10%` or `-5` (decrease) and
+/// `max` / `min`, plain numbers are raw values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrightnessChange {
+    Set(BrightnessAmount),
+    Increase(BrightnessAmount),
+    Decrease(BrightnessAmount),
+    Max,
+    Min,
+}
+
+impl BrightnessChange {
+    /// The raw brightness after applying the change to `backlight`
+    ///
+    /// Fails if an absolute value is out of range, relative changes are clamped.
+    pub fn apply(&self, backlight: &Backlight) -> Result<u16> {
+        match self {
+            Self::Set(amount) => backlight.raw(amount.unit, amount.value),
+            Self::Increase(amount) => Ok(backlight.step(amount.unit, amount.value.into())),
+            Self::Decrease(amount) => Ok(backlight.step(amount.unit, -i32::from(amount.value))),
+            Self::Max => Ok(backlight.max),
+            Self::Min => Ok(0),
+        }
+    }
+}
+
+impl core::str::FromStr for BrightnessAmount {
+    type Err = DDCError;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (value, unit) = match s.trim().strip_suffix('%') {
+            Some(percent) => (percent, BrightnessUnit::Percent),
+            None => (s.trim(), BrightnessUnit::Raw),
+        };
+        let value = value.trim();
+        // `u16::from_str` takes a leading `+`, which would make `++1` an increase
+        if !value.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(DDCError::new(DdcutilErrorKind::Other));
+        }
+        let value = value
+            .parse()
+            .map_err(|_| DDCError::new(DdcutilErrorKind::OutOfRange))?;
+        Ok(Self { value, unit })
+    }
+}
+
+impl core::str::FromStr for BrightnessChange {
+    type Err = DDCError;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("max") {
+            Ok(Self::Max)
+        } else if s.eq_ignore_ascii_case("min") {
+            Ok(Self::Min)
+        } else if let Some(amount) = s.strip_prefix('+') {
+            Ok(Self::Increase(amount.parse()?))
+        } else if let Some(amount) = s.strip_prefix('-') {
+            Ok(Self::Decrease(amount.parse()?))
+        } else {
+            Ok(Self::Set(s.parse()?))
+        }
+    }
+}
+
+impl core::fmt::Display for BrightnessAmount {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.unit {
+            BrightnessUnit::Percent => write!(f, "{}%", self.value),
+            BrightnessUnit::Raw => write!(f, "{}", self.value),
+        }
+    }
+}
+
+impl core::fmt::Display for BrightnessChange {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Set(amount) => write!(f, "{}", amount),
+            Self::Increase(amount) => write!(f, "+{}", amount),
+            Self::Decrease(amount) => write!(f, "-{}", amount),
+            Self::Max => write!(f, "max"),
+            Self::Min => write!(f, "min"),
+        }
+    }
+}
+
+/// Power mode (VCP 0xD6)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PowerMode {
+    On,
+    Standby,
+    Suspend,
+    /// Off until woken up over DDC/CI or by the power button
+    Off,
+    /// Switched off with the power button, most monitors don't answer anymore
+    PowerOff,
+}
+
+impl PowerMode {
+    /// The VCP 0xD6 value of the mode
+    pub fn code(self) -> u8 {
+        match self {
+            Self::On => 0x01,
+            Self::Standby => 0x02,
+            Self::Suspend => 0x03,
+            Self::Off => 0x04,
+            Self::PowerOff => 0x05,
+        }
+    }
+
+    /// The feature controlling the power of a monitor with these capabilities
+    ///
+    /// VCP 0xD6, or 0xE1 for monitors only listing that. Monitors whose capabilities can't be
+    /// read are assumed to support 0xD6.
+    pub(crate) fn feature(capabilities: Result<Capabilities>) -> Result<u8> {
+        match capabilities {
+            Ok(capabilities) if capabilities.supports(VCPFeature::POWER_MODE) => {
+                Ok(VCPFeature::POWER_MODE)
+            }
+            Ok(capabilities) if capabilities.supports(VCPFeature::POWER_CONTROL) => {
+                Ok(VCPFeature::POWER_CONTROL)
+            }
+            Ok(_) => {
+                tracing::error!("The monitor doesn't support VCP 0xd6 nor 0xe1");
+                Err(DDCError::new(DdcutilErrorKind::Unsupported))
+            }
+            Err(e) => {
+                tracing::debug!("Unable to check the power features: {:?}", e);
+                Ok(VCPFeature::POWER_MODE)
+            }
+        }
+    }
+
+    /// The power feature and the value to write to it to switch a monitor with these
+    /// capabilities to the mode
+    ///
+    /// Monitors listing the VCP 0xD6 values they accept are only switched to one of those.
+    pub(crate) fn setting(self, capabilities: Result<Capabilities>) -> Result<(u8, u16)> {
+        let accepted = capabilities
+            .as_ref()
+            .ok()
+            .and_then(|capabilities| capabilities.feature(VCPFeature::POWER_MODE))
+            .map(|feature| feature.values.clone())
+            .unwrap_or_default();
+        match (Self::feature(capabilities)?, self) {
+            (VCPFeature::POWER_CONTROL, Self::On) => Ok((VCPFeature::POWER_CONTROL, 1)),
+            (VCPFeature::POWER_CONTROL, Self::Off) => Ok((VCPFeature::POWER_CONTROL, 0)),
+            (VCPFeature::POWER_CONTROL, mode) => {
+                tracing::error!("The monitor can only be turned on or off, not to {}", mode);
+                Err(DDCError::new(DdcutilErrorKind::Unsupported))
+            }
+            (_, mode) if !accepted.is_empty() && !accepted.contains(&mode.code()) => {
+                tracing::error!("The monitor doesn't support the {} power mode", mode);
+                Err(DDCError::new(DdcutilErrorKind::Unsupported))
+            }
+            (code, mode) => Ok((code, mode.code().into())),
+        }
+    }
+}
+
+impl TryFrom<&VCPFeature> for PowerMode {
+    type Error = DDCError;
+    /// Read VCP 0xD6, or the on / off of 0xE1
+    fn try_from(feature: &VCPFeature) -> std::result::Result<Self, Self::Error> {
+        Ok(match (feature.code, feature.sl) {
+            (VCPFeature::POWER_CONTROL, 0) => Self::Off,
+            (VCPFeature::POWER_CONTROL, _) => Self::On,
+            (_, 0x01) => Self::On,
+            (_, 0x02) => Self::Standby,
+            (_, 0x03) => Self::Suspend,
+            (_, 0x04) => Self::Off,
+            (_, 0x05) => Self::PowerOff,
+            _ => return Err(DDCError::new(DdcutilErrorKind::OutOfRange)),
+        })
+    }
+}
+
+impl core::fmt::Display for PowerMode {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::On => "on",
+            Self::Standby => "standby",
+            Self::Suspend => "suspend",
+            Self::Off => "off",
+            Self::PowerOff => "power-off",
+        })
+    }
+}
+
+impl serde::Serialize for PowerMode {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Input source (VCP 0x60)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[allow(warnings)]
+pub enum Input {
+    VGA(u8),
+    DVI(u8),
+    Composite(u8),
+    SVideo(u8),
+    Tuner(u8),
+    Component(u8),
+    DP(u8),
+    HDMI(u8),
+    /// Not part of MCCS, but used by most monitors with a USB-C input (0x1b)
+    TYPEC(u8),
+    /// Any value not defined above
+    Raw(u8),
+}
+
+impl Input {
+    /// Every named input source
+    pub const ALL: &'static [Input] = &[
+        Self::VGA(1),
+        Self::VGA(2),
+        Self::DVI(1),
+        Self::DVI(2),
+        Self::Composite(1),
+        Self::Composite(2),
+        Self::SVideo(1),
+        Self::SVideo(2),
+        Self::Tuner(1),
+        Self::Tuner(2),
+        Self::Tuner(3),
+        Self::Component(1),
+        Self::Component(2),
+        Self::Component(3),
+        Self::DP(1),
+        Self::DP(2),
+        Self::HDMI(1),
+        Self::HDMI(2),
+        Self::TYPEC(1),
+    ];
+
+    /// The VCP value of the input, `None` for numbered inputs that don't exist (eg. `HDMI(3)`)
+    pub fn code(&self) -> Option<u8> {
+        Some(match self {
+            Self::VGA(n @ 1..=2) => *n,
+            Self::DVI(n @ 1..=2) => 0x02 + *n,
+            Self::Composite(n @ 1..=2) => 0x04 + *n,
+            Self::SVideo(n @ 1..=2) => 0x06 + *n,
+            Self::Tuner(n @ 1..=3) => 0x08 + *n,
+            Self::Component(n @ 1..=3) => 0x0b + *n,
+            Self::DP(n @ 1..=2) => 0x0e + *n,
+            Self::HDMI(n @ 1..=2) => 0x10 + *n,
+            Self::TYPEC(1) => 0x1b,
+            Self::Raw(value) => *value,
+            _ => return None,
+        })
+    }
+
+    /// The name of a named input source (eg. `HDMI-1`)
+    pub fn name(&self) -> Option<&'static str> {
+        Some(match self {
+            Self::VGA(1) => "VGA-1",
+            Self::VGA(2) => "VGA-2",
+            Self::DVI(1) => "DVI-1",
+            Self::DVI(2) => "DVI-2",
+            Self::Composite(1) => "Composite-1",
+            Self::Composite(2) => "Composite-2",
+            Self::SVideo(1) => "S-Video-1",
+            Self::SVideo(2) => "S-Video-2",
+            Self::Tuner(1) => "Tuner-1",
+            Self::Tuner(2) => "Tuner-2",
+            Self::Tuner(3) => "Tuner-3",
+            Self::Component(1) => "Component-1",
+            Self::Component(2) => "Component-2",
+            Self::Component(3) => "Component-3",
+            Self::DP(1) => "DP-1",
+            Self::DP(2) => "DP-2",
+            Self::HDMI(1) => "HDMI-1",
+            Self::HDMI(2) => "HDMI-2",
+            Self::TYPEC(1) => "USB-C",
+            _ => return None,
+        })
+    }
+}
+
+impl TryFrom<Input> for u8 {
+    type Error = DDCError;
+    fn try_from(input: Input) -> std::result::Result<u8, Self::Error> {
+        input
+            .code()
+            .ok_or_else(|| DDCError::new(DdcutilErrorKind::OutOfRange))
+    }
+}
+
+impl From<u8> for Input {
+    fn from(value: u8) -> Self {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|input| input.code() == Some(value))
+            .unwrap_or(Self::Raw(value))
+    }
+}
+
+impl core::fmt::Display for Input {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if let Some(name) = self.name() {
+            return f.write_str(name);
+        }
+        match self {
+            Self::VGA(n) => write!(f, "VGA-{}", n),
+            Self::DVI(n) => write!(f, "DVI-{}", n),
+            Self::Composite(n) => write!(f, "Composite-{}", n),
+            Self::SVideo(n) => write!(f, "S-Video-{}", n),
+            Self::Tuner(n) => write!(f, "Tuner-{}", n),
+            Self::Component(n) => write!(f, "Component-{}", n),
+            Self::DP(n) => write!(f, "DP-{}", n),
+            Self::HDMI(n) => write!(f, "HDMI-{}", n),
+            Self::TYPEC(n) => write!(f, "USB-C-{}", n),
+            Self::Raw(value) => write!(f, "{:#04x}", value),
+        }
+    }
+}
+
+impl serde::Serialize for Input {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Input {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let input = String::deserialize(deserializer)?;
+        input
+            .parse()
+            .map_err(|_| serde::de::Error::custom(format!("invalid input {:?}", input)))
+    }
+}
+
+/// Parses the names from the [`core::fmt::Display`] impl (case insensitive) or a raw value
+/// (`0x1b` or `27`)
+impl core::str::FromStr for Input {
+    type Err = DDCError;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let raw = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            Some(hex) => u8::from_str_radix(hex, 16).ok(),
+            None => s.parse().ok(),
+        };
+        if let Some(value) = raw {
+            return Ok(Self::from(value));
+        }
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|input| {
+                input
+                    .name()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(s))
+            })
+            .ok_or_else(|| DDCError::new(DdcutilErrorKind::Other))
+    }
+}
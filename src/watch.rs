@@ -0,0 +1,164 @@
+use crate::*;
+use core::time::Duration;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Arc;
+
+/// What happened to a display, see [`DisplayEvent`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayEventKind {
+    Connected,
+    Disconnected,
+    /// The display went to sleep (DPMS)
+    Asleep,
+    /// The display woke up from sleep (DPMS)
+    Awake,
+    /// The system resumed from suspend while the display was connected
+    Resumed,
+}
+
+/// A display appeared, went away, fell asleep or woke up
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DisplayEvent {
+    pub kind: DisplayEventKind,
+    /// The drm connector of the display (eg. `card1-DP-1`), empty if unknown
+    pub drm: String,
+    pub io_path: IOPath,
+}
+
+/// The events of a running watch, see [`DDCDriverTrait::watch`]
+///
+/// Iterating blocks until the next event arrives. Dropping the watch stops it.
+pub struct Watch {
+    events: Receiver<DisplayEvent>,
+    stop: Option<Box<dyn FnOnce()>>,
+}
+
+impl core::fmt::Display for DisplayEventKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::Connected => "connected",
+            Self::Disconnected => "disconnected",
+            Self::Asleep => "asleep",
+            Self::Awake => "awake",
+            Self::Resumed => "resumed",
+        })
+    }
+}
+
+impl DisplayEvent {
+    /// The drm connector without the card prefix (eg. `DP-1`)
+    pub fn connector(&self) -> &str {
+        self.drm.split_once('-').map(|s| s.1).unwrap_or(&self.drm)
+    }
+}
+
+impl Watch {
+    /// A watch over the events sent to `events`, ending once every sender is gone
+    pub fn new(events: Receiver<DisplayEvent>) -> Self {
+        Self { events, stop: None }
+    }
+
+    /// Run `stop` when the watch is dropped
+    pub fn on_stop(mut self, stop: impl FnOnce() + 'static) -> Self {
+        self.stop = Some(Box::new(stop));
+        self
+    }
+
+    /// Wait at most `timeout` for the next event
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<DisplayEvent> {
+        self.events.recv_timeout(timeout).ok()
+    }
+
+    /// Also report a [`DisplayEventKind::Resumed`] event for each display when the system resumes
+    /// from suspend
+    ///
+    /// `displays` are the ones connected now, the displays connected and disconnected later are
+    /// followed through the events. The time the system spent suspended is checked every few
+    /// seconds, monitors that stay connected don't report anything on their own.
+    pub fn with_resume(mut self, displays: Vec<DisplayEvent>) -> Self {
+        /// How often the suspended time is checked
+        const POLL: Duration = Duration::from_secs(2);
+        let (sender, events) = mpsc::channel();
+        let source = core::mem::replace(&mut self.events, events);
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stopping = Arc::clone(&stopped);
+        std::thread::spawn(move || {
+            let mut displays = displays;
+            let mut suspended = suspended_time();
+            while !stopped.load(Ordering::Relaxed) {
+                let event = match source.recv_timeout(POLL) {
+                    Ok(event) => Some(event),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+                let mut forward = Vec::new();
+                let now = suspended_time();
+                if now > suspended + Duration::from_secs(1) {
+                    tracing::info!("The system resumed after {:?}", now - suspended);
+                    forward.extend(displays.iter().map(|display| DisplayEvent {
+                        kind: DisplayEventKind::Resumed,
+                        ..display.clone()
+                    }));
+                }
+                suspended = now;
+                if let Some(event) = event {
+                    displays.retain(|display| display.io_path != event.io_path);
+                    if event.kind != DisplayEventKind::Disconnected {
+                        displays.push(event.clone());
+                    }
+                    forward.push(event);
+                }
+                if forward.into_iter().any(|event| sender.send(event).is_err()) {
+                    break;
+                }
+            }
+        });
+        let stop = self.stop.take();
+        self.on_stop(move || {
+            stopping.store(true, Ordering::Relaxed);
+            if let Some(stop) = stop {
+                stop();
+            }
+        })
+    }
+}
+
+/// How long the system has been suspended since it booted
+///
+/// `CLOCK_BOOTTIME` keeps running while the system is suspended, `CLOCK_MONOTONIC` doesn't.
+fn suspended_time() -> Duration {
+    let clock = |id| {
+        let mut time = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        unsafe { libc::clock_gettime(id, &mut time) };
+        Duration::new(time.tv_sec as u64, time.tv_nsec as u32)
+    };
+    clock(libc::CLOCK_BOOTTIME).saturating_sub(clock(libc::CLOCK_MONOTONIC))
+}
+
+impl Iterator for Watch {
+    type Item = DisplayEvent;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.events.recv().ok()
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            stop();
+        }
+    }
+}
+
+impl core::fmt::Debug for Watch {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Watch").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,126 @@
+use ddcbacklight::*;
+use std::path::{Path, PathBuf};
+
+fn edid(serial: u8) -> Vec<u8> {
+    let mut edid = vec![0; 256];
+    edid[..8].copy_from_slice(&Edid::HEADER);
+    edid[12] = serial;
+    edid
+}
+
+/// A fake `/sys/class/drm` in a temporary directory
+struct Sysfs(PathBuf);
+
+impl Sysfs {
+    fn new(name: &str) -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("ddcbacklight-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("card1")).unwrap();
+        Self(dir)
+    }
+
+    fn connector(&self, name: &str, status: &str, edid: &[u8], bus: Option<i32>) -> &Self {
+        let dir = self.0.join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("status"), format!("{}\n", status)).unwrap();
+        std::fs::write(dir.join("edid"), edid).unwrap();
+        if let Some(bus) = bus {
+            std::os::unix::fs::symlink(format!("../../i2c-{}", bus), dir.join("ddc")).unwrap();
+        }
+        self
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for Sysfs {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn cached(drm: &str, bus: i32, serial: u8) -> CachedDisplay {
+    let display = MockDisplay::new("DELL U2720Q", drm, bus);
+    let driver = MockDDC::new().with_display(display);
+    let ident = &driver.probe().unwrap()[0];
+    CachedDisplay {
+        edid: edid_hash(&edid(serial)),
+        bus,
+        identity: driver.identify(ident).unwrap(),
+    }
+}
+
+#[test]
+fn edid_hash_ignores_extensions() {
+    let mut extended = edid(1);
+    extended[200] = 0xff;
+    assert_eq!(edid_hash(&edid(1)), edid_hash(&extended));
+    assert_ne!(edid_hash(&edid(1)), edid_hash(&edid(2)));
+}
+
+#[test]
+fn validation() {
+    let sysfs = Sysfs::new("validation");
+    sysfs
+        .connector("card1-DP-1", "connected", &edid(1), Some(4))
+        .connector("card1-HDMI-A-1", "connected", &edid(2), None)
+        .connector("card1-DP-2", "disconnected", &[], None);
+    let cache = DetectionCache {
+        displays: vec![cached("card1-DP-1", 4, 1), cached("card1-HDMI-A-1", 5, 2)],
+        ..Default::default()
+    };
+    assert!(cache.is_valid(sysfs.path()));
+
+    // A monitor was moved to another bus
+    let moved = DetectionCache {
+        displays: vec![cached("card1-DP-1", 6, 1), cached("card1-HDMI-A-1", 5, 2)],
+        ..Default::default()
+    };
+    assert!(!moved.is_valid(sysfs.path()));
+
+    // A monitor was unplugged
+    let unplugged = DetectionCache {
+        displays: vec![cached("card1-DP-1", 4, 1)],
+        ..Default::default()
+    };
+    assert!(!unplugged.is_valid(sysfs.path()));
+
+    // A monitor the probe didn't find, like a laptop panel, is still connected
+    let mut ignoring = unplugged.clone();
+    ignoring.ignore_uncached(sysfs.path());
+    assert_eq!(ignoring.ignored, [edid_hash(&edid(2))]);
+    assert!(ignoring.is_valid(sysfs.path()));
+    sysfs.connector("card1-DP-1", "disconnected", &[], None);
+    assert!(!ignoring.is_valid(sysfs.path()));
+    sysfs.connector("card1-DP-1", "connected", &edid(1), Some(4));
+
+    // Another monitor is connected to the same port
+    sysfs.connector("card1-HDMI-A-1", "connected", &edid(3), None);
+    assert!(!cache.is_valid(sysfs.path()));
+
+    // A new monitor was plugged in
+    sysfs.connector("card1-HDMI-A-1", "connected", &edid(2), None);
+    sysfs.connector("card1-DP-2", "connected", &edid(4), Some(7));
+    assert!(!cache.is_valid(sysfs.path()));
+
+    assert!(!cache.is_valid(Path::new("/nonexistent")));
+}
+
+#[test]
+fn save_and_load() {
+    let sysfs = Sysfs::new("save");
+    let path = sysfs.path().join("cache").join("displays.json");
+    assert_eq!(DetectionCache::load(&path), None);
+    let cache = DetectionCache {
+        displays: vec![cached("card1-DP-1", 4, 1)],
+        ignored: vec![edid_hash(&edid(2))],
+    };
+    cache.save(&path).unwrap();
+    assert_eq!(DetectionCache::load(&path), Some(cache));
+    std::fs::write(&path, "{").unwrap();
+    assert_eq!(DetectionCache::load(&path), None);
+}
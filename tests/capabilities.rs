@@ -0,0 +1,224 @@
+use ddcbacklight::*;
+
+const CORPUS: &str = include_str!("data/capabilities.txt");
+
+fn corpus() -> impl Iterator<Item = &'static str> {
+    CORPUS
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+fn model(raw: &str) -> Option<String> {
+    Capabilities::parse(raw).unwrap().model
+}
+
+/// A small xorshift generator, so the property tests are reproducible without extra dependencies
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n.max(1) as u64) as usize
+    }
+
+    fn byte(&mut self) -> u8 {
+        self.next() as u8
+    }
+}
+
+#[test]
+fn corpus_parses() {
+    let mut count = 0;
+    for raw in corpus() {
+        let capabilities = Capabilities::parse(raw).unwrap();
+        assert!(capabilities.model.is_some(), "no model in {}", raw);
+        assert!(capabilities.supports(VCPFeature::BACKLIGHT), "{}", raw);
+        assert!(!capabilities.commands.is_empty(), "{}", raw);
+        count += 1;
+    }
+    assert!(count >= 10);
+}
+
+#[test]
+fn parse_tree() {
+    let parsed =
+        CapabilityString::parse("(prot(monitor)type(lcd)cmds(01 02 03)vcp(10 12 60(0F 11 12)))")
+            .unwrap();
+    assert_eq!(
+        parsed.segments,
+        vec![
+            Segment::Protocol("monitor".into()),
+            Segment::Type("lcd".into()),
+            Segment::Commands(vec![1, 2, 3]),
+            Segment::Vcp(vec![
+                VcpCapability {
+                    code: 0x10,
+                    values: vec![]
+                },
+                VcpCapability {
+                    code: 0x12,
+                    values: vec![]
+                },
+                VcpCapability {
+                    code: 0x60,
+                    values: vec![0x0f, 0x11, 0x12]
+                },
+            ]),
+        ]
+    );
+    assert_eq!(
+        parsed.to_string(),
+        "(prot(monitor)type(lcd)cmds(01 02 03)vcp(10 12 60(0F 11 12)))"
+    );
+}
+
+#[test]
+fn vendor_quirks() {
+    let inputs = |raw: &str| {
+        Capabilities::parse(raw)
+            .unwrap()
+            .feature(VCPFeature::INPUT)
+            .map(|f| f.values.clone())
+    };
+    // Hex bytes without separators, and a space before the values
+    assert_eq!(
+        Capabilities::parse("(vcp(021012 60 (0f11)))")
+            .unwrap()
+            .features,
+        vec![
+            VcpCapability {
+                code: 0x02,
+                values: vec![]
+            },
+            VcpCapability {
+                code: 0x10,
+                values: vec![]
+            },
+            VcpCapability {
+                code: 0x12,
+                values: vec![]
+            },
+            VcpCapability {
+                code: 0x60,
+                values: vec![0x0f, 0x11]
+            },
+        ]
+    );
+    // Repeated vcp segments are merged
+    let caps = Capabilities::parse("(VCP(10 60(0F))vcp(12 60(11)))").unwrap();
+    assert_eq!(caps.features.len(), 3);
+    assert_eq!(
+        inputs("(VCP(10 60(0F))vcp(12 60(11)))"),
+        Some(vec![0x0f, 0x11])
+    );
+    // 0x prefixes
+    assert_eq!(
+        inputs("(vcp(0x10 0x60(0x0F 0x11)))"),
+        Some(vec![0x0f, 0x11])
+    );
+    // Truncated, with trailing NUL bytes, without the outer parentheses
+    assert_eq!(
+        inputs("(model(A)vcp(10 60(0F 1\0\0"),
+        Some(vec![0x0f, 0x01])
+    );
+    assert_eq!(model("model(A)vcp(10)"), Some("A".into()));
+    assert_eq!(
+        model("(prot(monitor)) model( A ) ;vcp(10))"),
+        Some("A".into())
+    );
+    assert_eq!(
+        Capabilities::parse("(mccs_ver(2.1)mccs_ver(3.0))")
+            .unwrap()
+            .mccs_version,
+        Some("2.1".into())
+    );
+}
+
+#[test]
+fn malformed() {
+    for raw in ["", "()", "\0\0", "((()))", "not a capabilities string"] {
+        let err = Capabilities::parse(raw).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            DdcutilErrorKind::MalformedCapabilities
+        ));
+    }
+}
+
+/// Truncating, dropping, duplicating or replacing bytes of real strings never panics
+#[test]
+fn mutated_corpus() {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    let specials = b"()0x \0".to_vec();
+    for raw in corpus() {
+        for _ in 0..500 {
+            let mut bytes = raw.as_bytes().to_vec();
+            for _ in 0..=rng.below(4) {
+                let at = rng.below(bytes.len());
+                match rng.below(5) {
+                    0 => bytes.truncate(at),
+                    1 if !bytes.is_empty() => {
+                        bytes.remove(at);
+                    }
+                    2 => bytes.insert(at, specials[rng.below(specials.len())]),
+                    3 => bytes.insert(at, rng.byte()),
+                    _ => {
+                        let end = (at + rng.below(16)).min(bytes.len());
+                        let copy = bytes[at..end].to_vec();
+                        bytes.splice(at..at, copy);
+                    }
+                }
+            }
+            let mutated = String::from_utf8_lossy(&bytes);
+            if let Ok(parsed) = CapabilityString::parse(&mutated) {
+                let _ = Capabilities::from(parsed.clone());
+                let _ = parsed.to_string();
+            }
+        }
+    }
+}
+
+/// Printing a parsed string and parsing it again gives back the same tree
+#[test]
+fn round_trip() {
+    for raw in corpus() {
+        let parsed = CapabilityString::parse(raw).unwrap();
+        let printed = parsed.to_string();
+        assert_eq!(CapabilityString::parse(&printed).unwrap(), parsed);
+    }
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    let bytes = |rng: &mut Rng| {
+        let len = rng.below(8);
+        (0..len).map(|_| rng.byte()).collect::<Vec<_>>()
+    };
+    for _ in 0..1000 {
+        let mut segments = Vec::new();
+        for _ in 0..=rng.below(6) {
+            segments.push(match rng.below(5) {
+                0 => Segment::Model(format!("M{}", rng.below(1000))),
+                1 => Segment::MccsVersion(format!("{}.{}", rng.below(4), rng.below(10))),
+                2 => Segment::Commands(bytes(&mut rng)),
+                3 => Segment::Unknown {
+                    name: "mswhql".into(),
+                    value: rng.below(2).to_string(),
+                },
+                _ => Segment::Vcp(
+                    (0..rng.below(10))
+                        .map(|_| VcpCapability {
+                            code: rng.byte(),
+                            values: bytes(&mut rng),
+                        })
+                        .collect(),
+                ),
+            });
+        }
+        let tree = CapabilityString { segments };
+        assert_eq!(CapabilityString::parse(&tree.to_string()).unwrap(), tree);
+    }
+}
@@ -0,0 +1,886 @@
+use clap::Parser;
+use ddcbacklight::cli::{self, Args};
+use ddcbacklight::*;
+use ddcutil_sys::bindings::{DDCRC_REPORTED_UNSUPPORTED, DDCRC_RETRIES};
+
+/// The monitor on bus 4 of [`driver`]
+fn dell() -> MockDisplay {
+    MockDisplay::new("DELL U2720Q", "card1-DP-1", 4)
+}
+
+/// The monitor on bus 5 of [`driver`]
+fn lg() -> MockDisplay {
+    MockDisplay::new("LG HDR 4K", "card1-HDMI-A-1", 5)
+}
+
+/// The identity in the EDID of [`dell`]
+fn dell_edid(identity: &mut DisplayIdentity) {
+    identity.manufacturer = "DEL".into();
+    identity.product_code = 0xa0f2;
+    identity.serial = "5KC2R83".into();
+}
+
+/// A display event on i2c `bus`
+fn event(kind: DisplayEventKind, drm: &str, bus: i32) -> DisplayEvent {
+    DisplayEvent {
+        kind,
+        drm: drm.into(),
+        io_path: IOPath::I2C(bus),
+    }
+}
+
+fn monitors(dell: MockDisplay, lg: MockDisplay) -> MockDDC {
+    MockDDC::new().with_display(dell).with_display(lg)
+}
+
+fn driver() -> MockDDC {
+    monitors(
+        dell()
+            .with_vcp(VCPFeature::BACKLIGHT, 50, 100)
+            .with_vcp(VCPFeature::INPUT, 0x0f, 0x12),
+        lg().with_vcp(VCPFeature::BACKLIGHT, 95, 100)
+            .with_vcp(VCPFeature::INPUT, 0x11, 0x12),
+    )
+}
+
+/// Run the parsed `args` against `driver` and return the output
+///
+/// The claims of the fades go to a runtime directory of the tests, not to the one of whoever
+/// runs them.
+fn output(driver: &MockDDC, args: Args) -> Result<String> {
+    static RUNTIME_DIR: std::sync::Once = std::sync::Once::new();
+    RUNTIME_DIR.call_once(|| {
+        let dir = std::env::temp_dir().join(format!("ddcbacklight-tests-{}", std::process::id()));
+        std::env::set_var("XDG_RUNTIME_DIR", dir);
+    });
+    colored::control::set_override(false);
+    let mut out = Vec::new();
+    cli::run(driver, args, &mut out)?;
+    Ok(String::from_utf8(out).expect("Invalid UTF-8 in output"))
+}
+
+fn run(driver: &MockDDC, args: &[&str]) -> Result<String> {
+    let args = Args::try_parse_from(core::iter::once("xbacklight").chain(args.iter().copied()))
+        .expect("Failed to parse arguments");
+    output(driver, args)
+}
+
+fn status(err: DDCError) -> Option<i32> {
+    match err.kind() {
+        DdcutilErrorKind::LibDDCUtilError(e) => Some(e.status()),
+        _ => None,
+    }
+}
+
+#[test]
+fn get_brightness() {
+    let driver = driver();
+    let out = run(&driver, &["get"]).unwrap();
+    let lines: Vec<_> = out.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("DELL U2720Q"));
+    assert!(lines[0].contains("DP-1"));
+    assert!(lines[0].ends_with(" 50/100"));
+    assert!(lines[1].ends_with(" 95/100"));
+}
+
+#[test]
+fn set_brightness() {
+    let driver = driver();
+    let out = run(&driver, &["set", "30", "-n", "DP-1"]).unwrap();
+    assert_eq!(out.lines().count(), 1);
+    assert_eq!(driver.vcp(0, VCPFeature::BACKLIGHT), Some((30, 100)));
+    assert_eq!(driver.vcp(1, VCPFeature::BACKLIGHT), Some((95, 100)));
+}
+
+#[test]
+fn set_brightness_out_of_range() {
+    let driver = driver();
+    assert!(run(&driver, &["set", "101"]).is_err());
+    assert_eq!(driver.vcp(0, VCPFeature::BACKLIGHT), Some((50, 100)));
+}
+
+#[test]
+fn increase_brightness() {
+    let driver = driver();
+    run(&driver, &["inc", "10"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::BACKLIGHT), Some((60, 100)));
+    assert_eq!(driver.vcp(1, VCPFeature::BACKLIGHT), Some((100, 100)));
+}
+
+#[test]
+fn decrease_brightness() {
+    let driver = driver();
+    run(&driver, &["dec", "-b", "4"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::BACKLIGHT), Some((40, 100)));
+    assert_eq!(driver.vcp(1, VCPFeature::BACKLIGHT), Some((95, 100)));
+    run(&driver, &["dec", "60"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::BACKLIGHT), Some((0, 100)));
+}
+
+#[test]
+fn get_input() {
+    let driver = driver();
+    let out = run(&driver, &["get-input", "-b", "5"]).unwrap();
+    assert_eq!(out.lines().count(), 1);
+    assert!(out.contains("HDMI(1)"));
+    assert!(out.contains("/dev/i2c-5"));
+}
+
+#[test]
+fn set_input() {
+    let driver = driver();
+    let out = run(&driver, &["set-input", "-n", "DP-1", "HDMI-2"]).unwrap();
+    assert!(out.contains("HDMI(2)"));
+    assert_eq!(driver.vcp(0, VCPFeature::INPUT), Some((0x12, 0x12)));
+    assert_eq!(driver.vcp(1, VCPFeature::INPUT), Some((0x11, 0x12)));
+}
+
+#[test]
+fn completions() {
+    let out = run(&MockDDC::new(), &["completions", "bash"]).unwrap();
+    assert!(out.contains("xbacklight"));
+    let json = run(&MockDDC::new(), &["-o", "json", "completions", "bash"]).unwrap();
+    assert_eq!(json, out);
+}
+
+#[test]
+fn no_matching_display() {
+    let driver = driver();
+    assert_eq!(run(&driver, &["get", "-n", "eDP"]).unwrap(), "");
+}
+
+#[test]
+fn injected_failures() {
+    let driver = driver().with_display(
+        MockDisplay::new("BenQ", "card1-DP-2", 6)
+            .with_vcp(VCPFeature::BACKLIGHT, 50, 100)
+            .with_failure(MockOp::SetVcp(VCPFeature::BACKLIGHT), DDCRC_RETRIES),
+    );
+    run(&driver, &["get", "-b", "6"]).unwrap();
+    let err = run(&driver, &["set", "20", "-b", "6"]).unwrap_err();
+    assert_eq!(status(err), Some(DDCRC_RETRIES));
+    assert_eq!(driver.vcp(2, VCPFeature::BACKLIGHT), Some((50, 100)));
+
+    let err = run(&driver, &["get-input", "-b", "6"]).unwrap_err();
+    assert_eq!(status(err), Some(DDCRC_REPORTED_UNSUPPORTED));
+
+    let driver = MockDDC::new().with_probe_failure(DDCRC_RETRIES);
+    assert_eq!(
+        status(run(&driver, &["get"]).unwrap_err()),
+        Some(DDCRC_RETRIES)
+    );
+}
+
+#[test]
+fn latency() {
+    let driver = driver().with_latency(core::time::Duration::from_millis(20));
+    let start = std::time::Instant::now();
+    run(&driver, &["get", "-b", "4"]).unwrap();
+    assert!(start.elapsed() >= core::time::Duration::from_millis(40));
+}
+
+#[test]
+fn capabilities() {
+    let driver = MockDDC::new()
+        .with_display(MockDisplay::new("DELL", "card1-DP-1", 4).with_capabilities("(vcp(10))"));
+    let display = driver.probe().unwrap().remove(0);
+    assert_eq!(driver.capabilities(&display).unwrap(), "(vcp(10))");
+}
+
+#[test]
+fn set_input_raw() {
+    let driver = driver();
+    let out = run(&driver, &["set-input", "-b", "4", "usb-c"]).unwrap();
+    assert!(out.contains("TYPEC(1)"));
+    assert_eq!(driver.vcp(0, VCPFeature::INPUT), Some((0x1b, 0x12)));
+
+    let out = run(&driver, &["set-input", "-b", "4", "0x1c"]).unwrap();
+    assert!(out.contains("Raw(28)"));
+    assert_eq!(driver.vcp(0, VCPFeature::INPUT), Some((0x1c, 0x12)));
+
+    let args = Args::try_parse_from(["xbacklight", "set-input", "HDMI-3"]);
+    assert!(args.is_err());
+}
+
+#[test]
+fn input_table() {
+    for input in Input::ALL {
+        let code = u8::try_from(*input).unwrap();
+        assert_eq!(Input::from(code), *input);
+        assert_eq!(input.to_string().parse::<Input>().unwrap(), *input);
+    }
+    for code in 0..=u8::MAX {
+        assert_eq!(u8::try_from(Input::from(code)).unwrap(), code);
+    }
+    assert_eq!(Input::from(0x01), Input::VGA(1));
+    assert_eq!(Input::from(0x0c), Input::Component(1));
+    assert_eq!(Input::from(0x11), Input::HDMI(1));
+    assert_eq!(Input::from(0x40), Input::Raw(0x40));
+    assert!(u8::try_from(Input::HDMI(3)).is_err());
+}
+
+#[test]
+fn json_output() {
+    let driver = driver();
+    let out = run(&driver, &["-o", "json", "inc", "5", "-b", "4"]).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!([{
+            "model": "DELL U2720Q",
+            "drm": "card1-DP-1",
+            "io_path": "/dev/i2c-4",
+            "manufacturer": "",
+            "serial": "",
+            "product_code": 0,
+            "year": null,
+            "mccs_version": null,
+            "ddc": true,
+            "bus": 4,
+            "current": 55,
+            "max": 100,
+            "percent": 55,
+        }])
+    );
+
+    let out = run(&driver, &["get-input", "--output", "json"]).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(json[0]["input"], "DP-1");
+    assert_eq!(json[1]["input"], "HDMI-1");
+    assert!(json[0].get("current").is_none());
+}
+
+#[test]
+fn table_output() {
+    let driver = driver();
+    let out = run(&driver, &["get", "-o", "table"]).unwrap();
+    let lines: Vec<_> = out.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(
+        lines[0].split_whitespace().collect::<Vec<_>>(),
+        [
+            "MODEL",
+            "CONNECTOR",
+            "IO",
+            "PATH",
+            "BUS",
+            "CURRENT",
+            "MAX",
+            "PERCENT"
+        ]
+    );
+    assert_eq!(
+        lines[1].split_whitespace().collect::<Vec<_>>(),
+        [
+            "DELL",
+            "U2720Q",
+            "DP-1",
+            "/dev/i2c-4",
+            "4",
+            "50",
+            "100",
+            "50%"
+        ]
+    );
+}
+
+#[test]
+fn detect() {
+    let driver = driver().with_display(MockDisplay::new("BenQ", "card1-DP-2", 6).with_identity(
+        |identity| {
+            identity.manufacturer = "BNQ".into();
+            identity.serial = "XYZ987".into();
+            identity.product_code = 0x7f2a;
+            identity.year = Some(2021);
+            identity.mccs_version = Some("2.2".into());
+            identity.ddc = false;
+        },
+    ));
+    let out = run(&driver, &["detect"]).unwrap();
+    assert_eq!(out.matches("Display ").count(), 3);
+    assert!(out.contains("Display 2"));
+    assert!(out.contains("I2C bus:          /dev/i2c-6"));
+    assert!(out.contains("Manufacturer:     BNQ"));
+    assert!(out.contains("Product code:     32554 (0x7f2a)"));
+    assert!(out.contains("Manufacture year: 2021"));
+    assert!(out.contains("MCCS version:     2.2"));
+    assert!(out.contains("DDC capable:      no"));
+
+    let out = run(&driver, &["list", "-b", "6", "-o", "json"]).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(json[0]["index"], 2);
+    assert_eq!(json[0]["serial"], "XYZ987");
+    assert_eq!(json[0]["ddc"], false);
+
+    let out = run(&driver, &["detect", "-o", "table"]).unwrap();
+    assert!(out.lines().next().unwrap().starts_with("INDEX"));
+    assert_eq!(out.lines().count(), 4);
+}
+
+#[test]
+fn raw_capabilities() {
+    let caps = "(prot(monitor)type(lcd)model(U2720Q)cmds(01 02 03)vcp(10 12 60(0F 11)))";
+    let driver = MockDDC::new().with_display(dell().with_capabilities(caps));
+    let out = run(&driver, &["capabilities", "--raw"]).unwrap();
+    assert_eq!(out, format!("DELL U2720Q (DP-1):\n{}\n", caps));
+
+    let out = run(&driver, &["capabilities", "-r", "-o", "json"]).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(json[0]["raw_capabilities"], caps);
+}
+
+#[test]
+fn fade_brightness() {
+    // A bus no other test uses, so the claims of parallel tests don't cancel the fade
+    let driver = MockDDC::new().with_display(
+        MockDisplay::new("DELL U2720Q", "card1-DP-1", 90).with_vcp(VCPFeature::BACKLIGHT, 20, 100),
+    );
+    let out = run(&driver, &["inc", "40", "--time", "200", "--steps", "4"]).unwrap();
+    assert!(out.ends_with(" 60/100\n"));
+    let writes = driver.writes(0);
+    let values = writes.iter().map(|(_, value)| *value).collect::<Vec<_>>();
+    assert_eq!(values, [30, 40, 50, 60]);
+
+    run(
+        &driver,
+        &["set", "0", "--time", "50", "--easing", "ease-out"],
+    )
+    .unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::BACKLIGHT), Some((0, 100)));
+}
+
+#[test]
+fn brightness_units() {
+    let driver = monitors(
+        dell().with_vcp(VCPFeature::BACKLIGHT, 128, 255),
+        lg().with_vcp(VCPFeature::BACKLIGHT, 25, 50),
+    );
+    let out = run(&driver, &["get"]).unwrap();
+    assert!(out.lines().all(|line| line.ends_with(" 50/100")), "{}", out);
+    let out = run(&driver, &["get", "--unit", "raw"]).unwrap();
+    assert!(out.lines().next().unwrap().ends_with(" 128/255"));
+
+    run(&driver, &["set", "30%"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::BACKLIGHT), Some((77, 255)));
+    assert_eq!(driver.vcp(1, VCPFeature::BACKLIGHT), Some((15, 50)));
+    let out = run(&driver, &["get"]).unwrap();
+    assert!(out.lines().all(|line| line.ends_with(" 30/100")), "{}", out);
+
+    // Every percent step moves the brightness, even with less than 100 raw values
+    run(&driver, &["inc", "1%"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::BACKLIGHT), Some((79, 255)));
+    assert_eq!(driver.vcp(1, VCPFeature::BACKLIGHT), Some((16, 50)));
+
+    // Plain numbers are raw values whatever unit the output is in
+    run(&driver, &["set", "200", "-b", "4"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::BACKLIGHT), Some((200, 255)));
+    assert!(run(&driver, &["set", "256", "-b", "4"]).is_err());
+    run(&driver, &["inc", "100", "--unit", "percent"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::BACKLIGHT), Some((255, 255)));
+    assert_eq!(driver.vcp(1, VCPFeature::BACKLIGHT), Some((50, 50)));
+}
+
+fn run_as(program: &str, driver: &MockDDC, args: &[&str]) -> Result<String> {
+    let args = Args::parse_invocation(core::iter::once(program).chain(args.iter().copied()))
+        .expect("Failed to parse arguments");
+    output(driver, args)
+}
+
+#[test]
+fn xbacklight_syntax() {
+    let driver = driver();
+    assert_eq!(
+        run_as("xbacklight", &driver, &["-get"]).unwrap(),
+        "50\n95\n"
+    );
+    assert_eq!(
+        run_as("/usr/bin/xbacklight", &driver, &[])
+            .unwrap()
+            .lines()
+            .count(),
+        2
+    );
+
+    run_as("xbacklight", &driver, &["-set", "30.4", "-time", "0"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::BACKLIGHT), Some((30, 100)));
+    run_as(
+        "xbacklight",
+        &driver,
+        &["-inc", "10", "-time", "0", "-display", "DP-1"],
+    )
+    .unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::BACKLIGHT), Some((40, 100)));
+    assert_eq!(driver.vcp(1, VCPFeature::BACKLIGHT), Some((30, 100)));
+    run_as(
+        "xbacklight",
+        &driver,
+        &["-", "20", "-steps", "1", "-display", ":0"],
+    )
+    .unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::BACKLIGHT), Some((20, 100)));
+    assert_eq!(driver.vcp(1, VCPFeature::BACKLIGHT), Some((10, 100)));
+
+    let out = run_as("xbacklight", &driver, &["-get", "-o", "json"]).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(json[0]["current"], 20);
+
+    // The regular syntax keeps working, and the xbacklight one is only used under that name
+    run_as("xbacklight", &driver, &["set", "60"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::BACKLIGHT), Some((60, 100)));
+    assert!(Args::parse_invocation(["ddcbacklight", "-get"]).is_err());
+    assert!(Args::parse_invocation(["xbacklight", "-set"]).is_err());
+}
+
+#[test]
+fn brightness_expressions() {
+    let driver = driver();
+    run(&driver, &["set", "+10%", "-b", "4"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::BACKLIGHT), Some((60, 100)));
+    run(&driver, &["set", "-b", "4", "-5"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::BACKLIGHT), Some((55, 100)));
+    run(&driver, &["set", "max"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::BACKLIGHT), Some((100, 100)));
+    assert_eq!(driver.vcp(1, VCPFeature::BACKLIGHT), Some((100, 100)));
+    run(&driver, &["set", "min", "-n", "HDMI"]).unwrap();
+    assert_eq!(driver.vcp(1, VCPFeature::BACKLIGHT), Some((0, 100)));
+    run(&driver, &["dec", "25%", "-b", "4"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::BACKLIGHT), Some((75, 100)));
+    assert!(Args::try_parse_from(["xbacklight", "set", "bright"]).is_err());
+    assert!(Args::try_parse_from(["xbacklight", "set", "+-5"]).is_err());
+}
+
+#[test]
+fn watch() {
+    let driver = driver().with_events([
+        event(DisplayEventKind::Disconnected, "card1-DP-1", 4),
+        event(DisplayEventKind::Connected, "card1-DP-1", 4),
+        event(DisplayEventKind::Asleep, "card1-HDMI-A-1", 5),
+    ]);
+    let out = run(&driver, &["watch"]).unwrap();
+    let lines: Vec<_> = out.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], "disconnected DP-1 (/dev/i2c-4)");
+    assert!(lines[2].starts_with("asleep "));
+
+    let out = run(&driver, &["watch", "-o", "json"]).unwrap();
+    let events = out
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(events.len(), 3);
+    assert_eq!(events[1]["kind"], "connected");
+    assert_eq!(events[1]["drm"], "card1-DP-1");
+    assert_eq!(events[1]["io_path"], "/dev/i2c-4");
+}
+
+/// [`run`], changing the arguments that can't be given on the command line first
+fn run_configured(
+    driver: &MockDDC,
+    args: &[&str],
+    configure: impl FnOnce(&mut Args),
+) -> Result<String> {
+    let mut args =
+        Args::try_parse_from(core::iter::once("ddcbacklight").chain(args.iter().copied()))
+            .expect("Failed to parse arguments");
+    configure(&mut args);
+    output(driver, args)
+}
+
+fn run_with_state(driver: &MockDDC, state: &std::path::Path, args: &[&str]) -> Result<String> {
+    run_configured(driver, args, |args| args.state = Some(state.to_path_buf()))
+}
+
+#[test]
+fn restore() {
+    let state =
+        std::env::temp_dir().join(format!("ddcbacklight-restore-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&state);
+    // Only the first monitor has a serial number, the second one can't be told apart
+    let driver = || {
+        monitors(
+            dell()
+                .with_vcp(VCPFeature::BACKLIGHT, 50, 100)
+                .with_vcp(VCPFeature::INPUT, 0x0f, 0x12)
+                .with_identity(dell_edid),
+            lg().with_vcp(VCPFeature::BACKLIGHT, 95, 100),
+        )
+    };
+
+    let first = driver();
+    run_with_state(&first, &state, &["set", "30"]).unwrap();
+    run_with_state(&first, &state, &["set-input", "-b", "4", "HDMI-1"]).unwrap();
+    // Reading the brightness doesn't change what is remembered
+    run_with_state(&first, &state, &["get"]).unwrap();
+    let remembered = RestoreState::load(&state);
+    assert_eq!(remembered.displays.len(), 1);
+    let values = &remembered.displays["DEL-a0f2-5KC2R83"];
+    assert_eq!(values[&VCPFeature::BACKLIGHT], 30);
+    assert_eq!(values[&VCPFeature::INPUT], 0x11);
+
+    // The monitor comes back with its defaults
+    let second = driver().with_events([
+        event(DisplayEventKind::Disconnected, "card1-DP-1", 4),
+        event(DisplayEventKind::Connected, "card1-DP-1", 4),
+        event(DisplayEventKind::Awake, "card1-HDMI-A-1", 5),
+    ]);
+    run_with_state(&second, &state, &["watch"]).unwrap();
+    assert!(second.writes(0).is_empty());
+    run_with_state(&second, &state, &["watch", "--restore"]).unwrap();
+    assert_eq!(
+        second.writes(0),
+        [(VCPFeature::BACKLIGHT, 30), (VCPFeature::INPUT, 0x11)]
+    );
+    assert!(second.writes(1).is_empty());
+
+    // Monitors that stay connected while the system is suspended forget their values too
+    let resumed = driver().with_events([
+        event(DisplayEventKind::Resumed, "card1-DP-1", 4),
+        event(DisplayEventKind::Resumed, "card1-HDMI-A-1", 5),
+    ]);
+    let out = run_with_state(&resumed, &state, &["watch", "--restore"]).unwrap();
+    assert!(out.starts_with("resumed DP-1 (/dev/i2c-4)"), "{}", out);
+    assert_eq!(
+        resumed.writes(0),
+        [(VCPFeature::BACKLIGHT, 30), (VCPFeature::INPUT, 0x11)]
+    );
+    assert!(resumed.writes(1).is_empty());
+
+    // There is nowhere to restore from without a state
+    assert!(run(&second, &["watch", "--restore"]).is_err());
+    let _ = std::fs::remove_file(&state);
+}
+
+#[test]
+fn profiles() {
+    let dir = std::env::temp_dir().join(format!("ddcbacklight-profiles-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let run = |driver: &MockDDC, args: &[&str]| {
+        run_configured(driver, args, |args| args.profiles = Some(dir.clone()))
+    };
+    let driver = monitors(
+        dell()
+            .with_vcp(VCPFeature::BACKLIGHT, 50, 100)
+            .with_vcp(VCPFeature::CONTRAST, 75, 100)
+            .with_vcp(VCPFeature::INPUT, 0x0f, 0x12)
+            .with_identity(dell_edid),
+        lg().with_vcp(VCPFeature::BACKLIGHT, 95, 100),
+    );
+
+    // Only the features the monitors support are saved
+    let out = run(&driver, &["profile", "save", "day"]).unwrap();
+    assert_eq!(out.lines().count(), 4);
+    let profile = Profile::load(&dir.join("day.toml")).unwrap();
+    let saved = &profile.displays["DEL-a0f2-5KC2R83"];
+    assert_eq!(saved.model, "DELL U2720Q");
+    assert_eq!(
+        saved.values.iter().collect::<Vec<_>>(),
+        [
+            (&ProfileFeature::Brightness, &50),
+            (&ProfileFeature::Contrast, &75),
+            (&ProfileFeature::Input, &0x0f)
+        ]
+    );
+    // The second monitor has no serial number, it is told apart by its connector
+    assert_eq!(
+        profile.displays["-0000-HDMI-A-1"].values[&ProfileFeature::Brightness],
+        95
+    );
+
+    run(
+        &driver,
+        &[
+            "profile",
+            "save",
+            "night",
+            "--features",
+            "brightness",
+            "-b",
+            "4",
+        ],
+    )
+    .unwrap();
+    let profile = Profile::load(&dir.join("night.toml")).unwrap();
+    assert_eq!(profile.displays.len(), 1);
+    assert_eq!(profile.displays["DEL-a0f2-5KC2R83"].values.len(), 1);
+
+    run(&driver, &["set", "10"]).unwrap();
+    run(&driver, &["set-input", "HDMI-1", "-b", "4"]).unwrap();
+    let out = run(&driver, &["profile", "apply", "day", "-o", "json"]).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(json[0]["feature"], "brightness");
+    assert_eq!(json[0]["current"], 50);
+    assert_eq!(driver.vcp(0, VCPFeature::BACKLIGHT), Some((50, 100)));
+    assert_eq!(driver.vcp(0, VCPFeature::INPUT), Some((0x0f, 0x12)));
+    assert_eq!(driver.vcp(1, VCPFeature::BACKLIGHT), Some((95, 100)));
+
+    // A feature failing doesn't stop the others, but fails the command
+    let failing = MockDDC::new().with_display(
+        dell()
+            .with_vcp(VCPFeature::BACKLIGHT, 10, 100)
+            .with_vcp(VCPFeature::CONTRAST, 10, 100)
+            .with_vcp(VCPFeature::INPUT, 0x11, 0x12)
+            .with_identity(dell_edid)
+            .with_failure(MockOp::SetVcp(VCPFeature::CONTRAST), DDCRC_RETRIES),
+    );
+    assert!(run(&failing, &["profile", "apply", "day"]).is_err());
+    assert_eq!(
+        failing.writes(0),
+        [(VCPFeature::BACKLIGHT, 50), (VCPFeature::INPUT, 0x0f)]
+    );
+
+    assert!(run(&driver, &["profile", "apply", "missing"]).is_err());
+    assert!(run(&driver, &["profile", "save", "../day"]).is_err());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn config() {
+    let path =
+        std::env::temp_dir().join(format!("ddcbacklight-config-{}.toml", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"
+[monitors.desk]
+serial = "5KC2R83"
+step = 5
+min = 20
+max = 80
+input = "HDMI-1"
+
+[monitors.lg]
+model = "lg hdr 4k"
+"#,
+    )
+    .unwrap();
+    let config = Config::load(&path).unwrap();
+    let run = |driver: &MockDDC, args: &[&str]| {
+        run_configured(driver, args, |args| args.config = config.clone())
+    };
+    let driver = monitors(
+        dell()
+            .with_vcp(VCPFeature::BACKLIGHT, 50, 100)
+            .with_vcp(VCPFeature::INPUT, 0x0f, 0x12)
+            .with_identity(|identity| identity.serial = "5KC2R83".into()),
+        lg().with_vcp(VCPFeature::BACKLIGHT, 50, 100)
+            .with_vcp(VCPFeature::INPUT, 0x11, 0x12),
+    );
+
+    run(&driver, &["inc", "-m", "desk"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::BACKLIGHT), Some((55, 100)));
+    assert_eq!(driver.vcp(1, VCPFeature::BACKLIGHT), Some((50, 100)));
+    // Each monitor steps by its own amount
+    run(&driver, &["dec"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::BACKLIGHT), Some((50, 100)));
+    assert_eq!(driver.vcp(1, VCPFeature::BACKLIGHT), Some((40, 100)));
+
+    // The brightness stays within the limits of the monitor
+    run(&driver, &["set", "max"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::BACKLIGHT), Some((80, 100)));
+    assert_eq!(driver.vcp(1, VCPFeature::BACKLIGHT), Some((100, 100)));
+    run(&driver, &["dec", "90", "--monitor", "desk", "-m", "lg"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::BACKLIGHT), Some((20, 100)));
+    assert_eq!(driver.vcp(1, VCPFeature::BACKLIGHT), Some((10, 100)));
+
+    run(&driver, &["set-input", "-m", "desk"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::INPUT), Some((0x11, 0x12)));
+    assert!(run(&driver, &["set-input", "-m", "lg"]).is_err());
+    assert!(run(&driver, &["get", "-m", "tv"]).is_err());
+    assert!(Args::try_parse_from(["xbacklight", "get", "-m", "desk", "-n", "DP-1"]).is_err());
+
+    std::fs::write(&path, "[monitors.desk]\nbrightness = 5\n").unwrap();
+    assert!(Config::load(&path).is_err());
+    // The limits have to be percentages with the min below the max
+    for limits in ["max = 150", "min = 60\nmax = 40", "min = 101"] {
+        std::fs::write(
+            &path,
+            format!("[monitors.desk]\nserial = \"5KC2R83\"\n{}\n", limits),
+        )
+        .unwrap();
+        let e = Config::load(&path).unwrap_err();
+        assert!(e.kind().to_string().contains("monitor desk"), "{:?}", e);
+    }
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(Config::load(&path).unwrap(), Config::default());
+}
+
+#[test]
+fn selectors() {
+    let driver = driver();
+    let out = run(&driver, &["get", "-o", "value", "-s", "model=lg hdr 4k"]).unwrap();
+    assert_eq!(out, "95\n");
+    let out = run(
+        &driver,
+        &["get", "-o", "value", "-s", "bus=4", "-s", "bus=5"],
+    )
+    .unwrap();
+    assert_eq!(out, "50\n95\n");
+    let out = run(
+        &driver,
+        &["get", "-o", "value", "--exclude", "connector=DP"],
+    )
+    .unwrap();
+    assert_eq!(out, "95\n");
+    let out = run(&driver, &["get", "-o", "value", "-n", "DP", "-x", "bus=4"]).unwrap();
+    assert_eq!(out, "");
+    run(&driver, &["set", "10", "-s", "!display=0"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::BACKLIGHT), Some((50, 100)));
+    assert_eq!(driver.vcp(1, VCPFeature::BACKLIGHT), Some((10, 100)));
+    assert!(Args::try_parse_from(["xbacklight", "get", "-s", "size=27"]).is_err());
+    assert!(Args::try_parse_from(["xbacklight", "get", "-s", "bus=4", "-b", "4"]).is_err());
+}
+
+#[test]
+fn contrast() {
+    let driver = monitors(
+        dell()
+            .with_vcp(VCPFeature::CONTRAST, 75, 100)
+            .with_identity(|identity| identity.serial = "5KC2R83".into()),
+        lg().with_vcp(VCPFeature::CONTRAST, 35, 70),
+    );
+    let out = run(&driver, &["contrast", "get"]).unwrap();
+    let lines: Vec<_> = out.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].ends_with(" 75/100"));
+    assert!(lines[1].ends_with(" 50/100"));
+
+    run(&driver, &["contrast", "set", "60", "-n", "DP-1"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::CONTRAST), Some((60, 100)));
+    run(&driver, &["contrast", "inc", "-s", "bus=5"]).unwrap();
+    assert_eq!(driver.vcp(1, VCPFeature::CONTRAST), Some((42, 70)));
+    run(&driver, &["contrast", "dec", "5", "--unit", "raw"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::CONTRAST), Some((55, 100)));
+    assert_eq!(driver.vcp(1, VCPFeature::CONTRAST), Some((37, 70)));
+    run(&driver, &["contrast", "set", "+10%"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::CONTRAST), Some((65, 100)));
+
+    // Nothing changes if the value is out of range for any monitor
+    assert!(run(&driver, &["contrast", "set", "90", "--unit", "raw"]).is_err());
+    assert_eq!(driver.vcp(0, VCPFeature::CONTRAST), Some((65, 100)));
+
+    let out = run(&driver, &["contrast", "get", "-o", "json", "-b", "4"]).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(json[0]["feature"], "contrast");
+    assert_eq!(json[0]["current"], 65);
+    assert_eq!(json[0]["percent"], 65);
+
+    // The contrast is remembered for `watch --restore`
+    let state =
+        std::env::temp_dir().join(format!("ddcbacklight-contrast-{}.json", std::process::id()));
+    run_with_state(&driver, &state, &["contrast", "set", "40"]).unwrap();
+    let remembered = RestoreState::load(&state);
+    assert_eq!(remembered.displays.len(), 1);
+    assert_eq!(
+        remembered.displays.values().next().unwrap()[&VCPFeature::CONTRAST],
+        40
+    );
+    let _ = std::fs::remove_file(&state);
+}
+
+#[test]
+fn volume_and_mute() {
+    let driver = monitors(
+        dell()
+            .with_vcp(VCPFeature::VOLUME, 30, 100)
+            .with_vcp(VCPFeature::MUTE, 2, 2)
+            .with_capabilities("(prot(monitor)vcp(10 12 62 8D(01 02))mccs_ver(2.1))"),
+        lg().with_vcp(VCPFeature::VOLUME, 10, 50)
+            .with_vcp(VCPFeature::MUTE, 1, 2),
+    );
+    let out = run(&driver, &["volume", "get"]).unwrap();
+    let lines: Vec<_> = out.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].ends_with(" 30/100"));
+    assert!(lines[1].ends_with(" 20/100"));
+    run(&driver, &["volume", "set", "50", "-b", "4"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::VOLUME), Some((50, 100)));
+    run(&driver, &["volume", "inc", "-b", "5"]).unwrap();
+    assert_eq!(driver.vcp(1, VCPFeature::VOLUME), Some((15, 50)));
+    run(&driver, &["volume", "dec", "5", "--unit", "raw", "-b", "4"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::VOLUME), Some((45, 100)));
+    let out = run(&driver, &["volume", "get", "-o", "json", "-b", "4"]).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(json[0]["feature"], "volume");
+    assert_eq!(json[0]["current"], 45);
+
+    run(&driver, &["mute", "on", "-b", "4"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::MUTE), Some((1, 2)));
+    let out = run(&driver, &["mute", "toggle", "-o", "value"]).unwrap();
+    assert_eq!(out, "off\noff\n");
+    assert_eq!(driver.vcp(0, VCPFeature::MUTE), Some((2, 2)));
+    assert_eq!(driver.vcp(1, VCPFeature::MUTE), Some((2, 2)));
+    let out = run(&driver, &["mute", "off", "-b", "5"]).unwrap();
+    assert!(out.trim_end().ends_with(" unmuted"));
+}
+
+#[test]
+fn volume_unsupported() {
+    let driver = monitors(
+        dell()
+            .with_vcp(VCPFeature::VOLUME, 30, 100)
+            .with_vcp(VCPFeature::MUTE, 2, 2)
+            .with_capabilities("(prot(monitor)vcp(10 12 62 8D)mccs_ver(2.1))"),
+        lg().with_vcp(VCPFeature::VOLUME, 10, 50)
+            .with_vcp(VCPFeature::MUTE, 2, 2)
+            .with_capabilities("(prot(monitor)vcp(10 12)mccs_ver(2.2))"),
+    );
+    let err = run(&driver, &["volume", "get", "-b", "5"]).unwrap_err();
+    assert!(err.is_unsupported());
+    // Nothing changes if any monitor lacks the feature
+    assert!(run(&driver, &["volume", "set", "80"]).is_err());
+    assert_eq!(driver.vcp(0, VCPFeature::VOLUME), Some((30, 100)));
+    assert!(run(&driver, &["mute", "on"]).is_err());
+    assert_eq!(driver.vcp(0, VCPFeature::MUTE), Some((2, 2)));
+    run(&driver, &["mute", "on", "-b", "4"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::MUTE), Some((1, 2)));
+}
+
+#[test]
+fn power() {
+    let driver = monitors(
+        dell()
+            .with_vcp(VCPFeature::POWER_MODE, 1, 5)
+            .with_capabilities("(prot(monitor)vcp(10 D6(01 04 05))mccs_ver(2.1))"),
+        lg().with_vcp(VCPFeature::POWER_CONTROL, 1, 1)
+            .with_capabilities("(prot(monitor)vcp(10 E1)mccs_ver(2.2))"),
+    )
+    .with_display(
+        MockDisplay::new("BenQ GW2480", "card1-DP-2", 6)
+            .with_capabilities("(prot(monitor)vcp(10 12)mccs_ver(2.2))"),
+    );
+    let out = run(
+        &driver,
+        &["power", "get", "-o", "value", "-s", "bus=4|bus=5"],
+    )
+    .unwrap();
+    assert_eq!(out, "on\non\n");
+
+    run(&driver, &["power", "off", "-b", "4"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::POWER_MODE), Some((4, 5)));
+    let out = run(&driver, &["power", "get", "-o", "json", "-b", "4"]).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(json[0]["power"], "off");
+    // Modes missing from the D6 values of the capabilities aren't written
+    assert!(run(&driver, &["power", "standby", "-b", "4"])
+        .unwrap_err()
+        .is_unsupported());
+    assert_eq!(driver.vcp(0, VCPFeature::POWER_MODE), Some((4, 5)));
+    run(&driver, &["power", "on", "-b", "4"]).unwrap();
+    assert_eq!(driver.vcp(0, VCPFeature::POWER_MODE), Some((1, 5)));
+
+    // Monitors with only 0xE1 can be turned on and off
+    run(&driver, &["power", "off", "-b", "5"]).unwrap();
+    assert_eq!(driver.vcp(1, VCPFeature::POWER_CONTROL), Some((0, 1)));
+    let out = run(&driver, &["power", "get", "-o", "value", "-b", "5"]).unwrap();
+    assert_eq!(out, "off\n");
+    run(&driver, &["power", "on", "-b", "5"]).unwrap();
+    assert_eq!(driver.vcp(1, VCPFeature::POWER_CONTROL), Some((1, 1)));
+    assert!(run(&driver, &["power", "suspend", "-b", "5"])
+        .unwrap_err()
+        .is_unsupported());
+
+    assert!(run(&driver, &["power", "get", "-b", "6"])
+        .unwrap_err()
+        .is_unsupported());
+    assert!(Args::try_parse_from(["xbacklight", "power", "power-off"]).is_err());
+}
@@ -0,0 +1,173 @@
+use clap::Parser;
+use ddcbacklight::cli::{self, Args};
+use ddcbacklight::*;
+
+fn args(args: &[&str]) -> Args {
+    Args::try_parse_from(core::iter::once("xbacklight").chain(args.iter().copied()))
+        .expect("Failed to parse arguments")
+}
+
+fn strings(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+/// Start a daemon serving a mock display on a socket of its own
+fn daemon(name: &str) -> Daemon {
+    serving(name, || {
+        MockDDC::new().with_display(
+            MockDisplay::new("DELL U2720Q", "card1-DP-1", 4)
+                .with_vcp(VCPFeature::BACKLIGHT, 50, 100)
+                .with_vcp(VCPFeature::INPUT, 0x0f, 0x12),
+        )
+    })
+}
+
+/// Start a daemon serving the displays of `driver` on a socket of its own
+///
+/// The claims of the fades go to a runtime directory of the tests, not to the one of whoever
+/// runs them.
+fn serving(name: &str, driver: fn() -> MockDDC) -> Daemon {
+    static RUNTIME_DIR: std::sync::Once = std::sync::Once::new();
+    RUNTIME_DIR.call_once(|| {
+        let dir = std::env::temp_dir().join(format!("ddcbacklight-tests-{}", std::process::id()));
+        std::env::set_var("XDG_RUNTIME_DIR", dir);
+    });
+    let socket =
+        std::env::temp_dir().join(format!("ddcbacklight-{}-{}.sock", name, std::process::id()));
+    let path = socket.clone();
+    std::thread::spawn(move || {
+        Daemon::new(path).serve(&driver()).unwrap();
+    });
+    let daemon = Daemon::new(socket);
+    for _ in 0..100 {
+        if daemon.request(&strings(&["get"]), false).is_some() {
+            return daemon;
+        }
+        std::thread::sleep(core::time::Duration::from_millis(10));
+    }
+    panic!("The daemon didn't start");
+}
+
+#[test]
+fn serve() {
+    let daemon = daemon("serve");
+    let out = daemon
+        .request(&strings(&["get", "-o", "value"]), false)
+        .unwrap()
+        .unwrap();
+    assert_eq!(out, "50\n");
+    daemon
+        .request(&strings(&["set", "30"]), false)
+        .unwrap()
+        .unwrap();
+    daemon
+        .request(&strings(&["inc", "5"]), false)
+        .unwrap()
+        .unwrap();
+    let out = daemon
+        .request(&strings(&["get", "-o", "value"]), false)
+        .unwrap()
+        .unwrap();
+    assert_eq!(out, "35\n");
+    // Invalid commands are rejected without stopping the daemon
+    assert!(daemon
+        .request(&strings(&["frobnicate"]), false)
+        .unwrap()
+        .is_err());
+    assert!(daemon
+        .request(&strings(&["daemon"]), false)
+        .unwrap()
+        .is_err());
+    assert!(daemon.request(&strings(&["get"]), false).unwrap().is_ok());
+}
+
+#[test]
+fn concurrent_clients() {
+    let daemon = daemon("concurrent");
+    let clients = (0..10)
+        .map(|_| {
+            let daemon = daemon.clone();
+            std::thread::spawn(move || daemon.request(&strings(&["dec", "1"]), false))
+        })
+        .collect::<Vec<_>>();
+    for client in clients {
+        assert!(client.join().unwrap().unwrap().is_ok());
+    }
+    let out = daemon
+        .request(&strings(&["get", "-o", "value"]), false)
+        .unwrap()
+        .unwrap();
+    assert_eq!(out, "40\n");
+}
+
+#[test]
+fn fades_of_other_displays() {
+    let daemon = serving("fades", || {
+        MockDDC::new()
+            .with_display(MockDisplay::new("DELL U2720Q", "card1-DP-1", 4).with_vcp(
+                VCPFeature::BACKLIGHT,
+                0,
+                100,
+            ))
+            .with_display(MockDisplay::new("LG HDR 4K", "card1-HDMI-A-1", 5).with_vcp(
+                VCPFeature::BACKLIGHT,
+                50,
+                100,
+            ))
+    });
+    let request = |args: &'static [&'static str]| {
+        let daemon = daemon.clone();
+        std::thread::spawn(move || daemon.request(&strings(args), false).unwrap())
+    };
+    let fade = request(&["set", "100%", "-b", "4", "--time", "400", "--steps", "10"]);
+    std::thread::sleep(core::time::Duration::from_millis(100));
+    // A change of another display doesn't stop the fade
+    request(&["set", "10%", "-b", "5"]).join().unwrap().unwrap();
+    fade.join().unwrap().unwrap();
+    let out = daemon
+        .request(&strings(&["get", "-o", "value"]), false)
+        .unwrap()
+        .unwrap();
+    assert_eq!(out, "100\n10\n");
+
+    // A change of the same display does
+    let fade = request(&["set", "0%", "-b", "4", "--time", "400", "--steps", "10"]);
+    std::thread::sleep(core::time::Duration::from_millis(100));
+    request(&["inc", "0%", "-b", "4"]).join().unwrap().unwrap();
+    fade.join().unwrap().unwrap();
+    let out = daemon
+        .request(&strings(&["get", "-o", "value", "-b", "4"]), false)
+        .unwrap()
+        .unwrap();
+    assert!(out.trim().parse::<u16>().unwrap() > 0, "{}", out);
+}
+
+#[test]
+fn no_daemon() {
+    let daemon = Daemon::new(std::env::temp_dir().join("ddcbacklight-missing.sock"));
+    assert!(daemon.request(&strings(&["get"]), false).is_none());
+}
+
+#[test]
+fn merge_changes() {
+    let merged = |first: &[&str], next: &[&str]| {
+        Daemon::merge(&args(first), &args(next)).map(|args| match args.op {
+            cli::Op::SetBrightness { brightness, .. } => brightness.to_string(),
+            op => panic!("Unexpected {:?}", op),
+        })
+    };
+    assert_eq!(merged(&["inc", "5"], &["inc", "10"]), Some("+15".into()));
+    assert_eq!(merged(&["inc", "5"], &["dec", "10"]), Some("-5".into()));
+    assert_eq!(merged(&["set", "-5%"], &["set", "+5%"]), Some("+0%".into()));
+    assert_eq!(merged(&["inc", "5"], &["set", "80"]), Some("80".into()));
+    assert_eq!(merged(&["set", "80"], &["set", "max"]), Some("max".into()));
+    // An absolute change can't be merged with a relative one after it
+    assert_eq!(merged(&["set", "80"], &["inc", "5"]), None);
+    // Different units, displays or fades
+    assert_eq!(merged(&["inc", "5"], &["inc", "5%"]), None);
+    assert_eq!(merged(&["inc", "5"], &["inc", "5", "-n", "DP-1"]), None);
+    assert_eq!(merged(&["inc", "5"], &["inc", "5", "--time", "100"]), None);
+    assert_eq!(merged(&["inc", "5"], &["get"]), None);
+    // The step of each monitor is only known once they are probed
+    assert_eq!(merged(&["inc"], &["inc"]), None);
+}
@@ -0,0 +1,165 @@
+#![cfg(feature = "dbus")]
+use ddcbacklight::dbus::{object_path, BUS_NAME, DISPLAY_INTERFACE};
+use ddcbacklight::*;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+use zbus::blocking::{fdo::DBusProxy, fdo::PropertiesProxy, Connection};
+use zbus::proxy::CacheProperties;
+
+#[zbus::proxy(
+    interface = "org.ddcbacklight.Display",
+    default_service = "org.ddcbacklight.DDCBacklight"
+)]
+trait Monitor {
+    #[zbus(property)]
+    fn model(&self) -> zbus::Result<String>;
+    #[zbus(property)]
+    fn connector(&self) -> zbus::Result<String>;
+    #[zbus(property)]
+    fn brightness(&self) -> zbus::Result<u16>;
+    #[zbus(property)]
+    fn set_brightness(&self, percent: u16) -> zbus::Result<()>;
+    #[zbus(property)]
+    fn input(&self) -> zbus::Result<String>;
+    fn get_vcp(&self, code: u8) -> zbus::Result<(u16, u16)>;
+    fn set_vcp(&self, code: u8, value: u16) -> zbus::Result<()>;
+}
+
+/// A private `dbus-daemon`, killed when dropped
+struct Bus {
+    daemon: Child,
+    address: String,
+}
+
+impl Bus {
+    /// `None` if `dbus-daemon` isn't installed
+    fn start() -> Option<Self> {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.as_mut()?)
+            .read_line(&mut address)
+            .ok()?;
+        Some(Self {
+            daemon,
+            address: address.trim().to_string(),
+        })
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+/// Serve a mock display answering after `latency` on `bus` and wait for the service to show up
+fn serve(bus: &Bus, latency: Duration) -> Connection {
+    let address = bus.address.clone();
+    std::thread::spawn(move || {
+        let driver = MockDDC::new().with_display(
+            MockDisplay::new("DELL U2720Q", "card1-DP-1", 4)
+                .with_vcp(VCPFeature::BACKLIGHT, 25, 50)
+                .with_vcp(VCPFeature::INPUT, 0x0f, 0x12)
+                .with_latency(latency),
+        );
+        dbus::serve(&driver, Some(&address)).unwrap();
+    });
+    let connection = zbus::blocking::connection::Builder::address(bus.address.as_str())
+        .unwrap()
+        .build()
+        .unwrap();
+    let names = DBusProxy::new(&connection).unwrap();
+    for _ in 0..200 {
+        if names.name_has_owner(BUS_NAME.try_into().unwrap()).unwrap() {
+            return connection;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    panic!("The service didn't show up on the bus");
+}
+
+/// Read the properties from the service every time, not from the cache of the proxy
+fn proxy(connection: &Connection) -> MonitorProxyBlocking<'_> {
+    MonitorProxyBlocking::builder(connection)
+        .path(object_path(&IOPath::I2C(4)))
+        .unwrap()
+        .cache_properties(CacheProperties::No)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn display_object() {
+    let Some(bus) = Bus::start() else {
+        eprintln!("dbus-daemon not found, skipping");
+        return;
+    };
+    let connection = serve(&bus, Duration::ZERO);
+    let path = object_path(&IOPath::I2C(4));
+    let display = proxy(&connection);
+
+    assert_eq!(display.model().unwrap(), "DELL U2720Q");
+    assert_eq!(display.connector().unwrap(), "DP-1");
+    assert_eq!(display.brightness().unwrap(), 50);
+    assert_eq!(display.input().unwrap(), Input::DP(1).to_string());
+    let (current, max) = display.get_vcp(VCPFeature::BACKLIGHT).unwrap();
+    assert_eq!((current, max), (25, 50));
+
+    let properties = PropertiesProxy::builder(&connection)
+        .destination(BUS_NAME)
+        .unwrap()
+        .path(path.as_str())
+        .unwrap()
+        .build()
+        .unwrap();
+    let mut changes = properties.receive_properties_changed().unwrap();
+
+    display.set_brightness(80).unwrap();
+    let (current, _) = display.get_vcp(VCPFeature::BACKLIGHT).unwrap();
+    assert_eq!(current, 40);
+    let changed = changes.next().unwrap();
+    let args = changed.args().unwrap();
+    assert_eq!(args.interface_name().as_str(), DISPLAY_INTERFACE);
+    assert!(args.changed_properties().contains_key("Brightness"));
+
+    display.set_vcp(VCPFeature::INPUT, 0x11).unwrap();
+    assert_eq!(display.input().unwrap(), Input::HDMI(1).to_string());
+    let changed = changes.next().unwrap();
+    assert!(changed
+        .args()
+        .unwrap()
+        .changed_properties()
+        .contains_key("Input"));
+
+    // Out of range values are rejected
+    assert!(display.set_brightness(101).is_err());
+}
+
+#[test]
+fn slow_monitor() {
+    let Some(bus) = Bus::start() else {
+        eprintln!("dbus-daemon not found, skipping");
+        return;
+    };
+    let connection = serve(&bus, Duration::from_millis(500));
+    let writer = connection.clone();
+    let write = std::thread::spawn(move || proxy(&writer).set_vcp(VCPFeature::BACKLIGHT, 40));
+    std::thread::sleep(Duration::from_millis(100));
+
+    // The service still answers while the monitor is busy with the write
+    let start = Instant::now();
+    assert_eq!(proxy(&connection).model().unwrap(), "DELL U2720Q");
+    assert!(
+        start.elapsed() < Duration::from_millis(250),
+        "{:?}",
+        start.elapsed()
+    );
+    write.join().unwrap().unwrap();
+}
@@ -0,0 +1,189 @@
+use ddcbacklight::*;
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+fn driver() -> MockDDC {
+    MockDDC::new()
+        .with_display(MockDisplay::new("DELL U2720Q", "card1-DP-1", 4).with_vcp(
+            VCPFeature::BACKLIGHT,
+            0,
+            100,
+        ))
+        .with_display(
+            MockDisplay::new("LG HDR 4K", "card1-HDMI-A-1", 5)
+                .with_vcp(VCPFeature::BACKLIGHT, 100, 100)
+                .with_latency(Duration::from_millis(30)),
+        )
+}
+
+fn targets(driver: &MockDDC) -> Vec<FadeTarget> {
+    let displays = driver.probe().unwrap();
+    vec![
+        FadeTarget {
+            display: displays[0].clone(),
+            from: 0,
+            to: 100,
+        },
+        FadeTarget {
+            display: displays[1].clone(),
+            from: 100,
+            to: 0,
+        },
+    ]
+}
+
+fn brightness(driver: &MockDDC, index: usize) -> Vec<u16> {
+    driver
+        .writes(index)
+        .into_iter()
+        .map(|(code, value)| {
+            assert_eq!(code, VCPFeature::BACKLIGHT);
+            value
+        })
+        .collect()
+}
+
+#[test]
+fn easing() {
+    for easing in [
+        Easing::Linear,
+        Easing::EaseIn,
+        Easing::EaseOut,
+        Easing::EaseInOut,
+    ] {
+        assert_eq!(easing.apply(0.0), 0.0);
+        assert_eq!(easing.apply(1.0), 1.0);
+        assert_eq!(easing.apply(2.0), 1.0);
+        let samples = (0..=10).map(|t| easing.apply(t as f64 / 10.0));
+        let samples = samples.collect::<Vec<_>>();
+        assert!(samples.windows(2).all(|w| w[0] <= w[1]), "{:?}", easing);
+    }
+    assert!(Easing::EaseIn.apply(0.5) < 0.5);
+    assert!(Easing::EaseOut.apply(0.5) > 0.5);
+    assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+}
+
+#[test]
+fn values() {
+    let fade = Fade::new(Duration::from_millis(100), 4);
+    let values = (0..=4).map(|step| fade.value(10, 90, step));
+    assert_eq!(values.collect::<Vec<_>>(), [10, 30, 50, 70, 90]);
+    let values = (0..=4).map(|step| fade.value(90, 10, step));
+    assert_eq!(values.collect::<Vec<_>>(), [90, 70, 50, 30, 10]);
+}
+
+#[test]
+fn instant() {
+    let driver = driver();
+    Fade::default()
+        .run(&driver, &targets(&driver), |_| true)
+        .unwrap();
+    assert_eq!(brightness(&driver, 0), [100]);
+    assert_eq!(brightness(&driver, 1), [0]);
+}
+
+#[test]
+fn parallel() {
+    let driver = driver();
+    let fade = Fade::new(Duration::from_millis(200), 10);
+    let start = Instant::now();
+    fade.run(&driver, &targets(&driver), |_| false).unwrap();
+    let elapsed = start.elapsed();
+    // Both displays fade at the same time instead of one after the other
+    assert!(elapsed >= Duration::from_millis(200), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(380), "{:?}", elapsed);
+
+    let fast = brightness(&driver, 0);
+    assert!(fast.windows(2).all(|w| w[0] < w[1]), "{:?}", fast);
+    assert_eq!(fast.last(), Some(&100));
+
+    // The slow display skips steps to keep up, but still ends on the target
+    let slow = brightness(&driver, 1);
+    assert!(slow.len() < 10, "{:?}", slow);
+    assert!(slow.windows(2).all(|w| w[0] > w[1]), "{:?}", slow);
+    assert_eq!(slow.last(), Some(&0));
+}
+
+#[test]
+fn slow_display() {
+    let slow = Duration::from_millis(80);
+    let driver = MockDDC::new()
+        .with_display(MockDisplay::new("DELL U2720Q", "card1-DP-1", 4).with_vcp(
+            VCPFeature::BACKLIGHT,
+            0,
+            100,
+        ))
+        .with_display(
+            MockDisplay::new("LG HDR 4K", "card1-HDMI-A-1", 5)
+                .with_vcp(VCPFeature::BACKLIGHT, 100, 100)
+                .with_latency(slow),
+        );
+    let fade = Fade::new(Duration::from_millis(400), 20);
+    let start = Instant::now();
+    let steps = std::cell::RefCell::new(Vec::new());
+    fade.run(&driver, &targets(&driver), |display| {
+        if display.index == 0 {
+            steps.borrow_mut().push(start.elapsed());
+        }
+        false
+    })
+    .unwrap();
+
+    // The fast display only ever waits for a single write of the slow one, and isn't dragged
+    // past the end of the fade by it
+    let steps = steps.into_inner();
+    let mut times = core::iter::once(Duration::ZERO).chain(steps.iter().copied());
+    let mut previous = times.next().unwrap();
+    for time in times {
+        assert!(
+            time - previous < slow + Duration::from_millis(40),
+            "{:?}",
+            steps
+        );
+        previous = time;
+    }
+    assert!(previous < Duration::from_millis(400) + slow, "{:?}", steps);
+    let fast = brightness(&driver, 0);
+    assert!(fast.windows(2).all(|w| w[0] < w[1]), "{:?}", fast);
+    assert_eq!(fast.last(), Some(&100));
+    assert_eq!(brightness(&driver, 1).last(), Some(&0));
+}
+
+#[test]
+fn cancelled() {
+    let driver = driver();
+    let fade = Fade::new(Duration::from_millis(300), 10).with_easing(Easing::EaseIn);
+    let checks = Cell::new(0);
+    fade.run(&driver, &targets(&driver)[..1], |_| {
+        checks.set(checks.get() + 1);
+        checks.get() > 3
+    })
+    .unwrap();
+    assert_eq!(brightness(&driver, 0).len(), 3);
+    assert_eq!(driver.vcp(0, VCPFeature::BACKLIGHT), Some((9, 100)));
+}
+
+#[test]
+fn claims() {
+    let dir = std::env::temp_dir().join(format!("ddcbacklight-claims-{}", std::process::id()));
+    let older = Claims::new(&dir);
+    older.claim(&IOPath::I2C(4)).unwrap();
+    assert!(older.is_claimed(&IOPath::I2C(4)));
+    assert!(older.is_claimed(&IOPath::I2C(5)));
+
+    std::thread::sleep(Duration::from_millis(1));
+    let newer = Claims::new(&dir);
+    newer.claim(&IOPath::I2C(4)).unwrap();
+    assert!(!older.is_claimed(&IOPath::I2C(4)));
+    assert!(newer.is_claimed(&IOPath::I2C(4)));
+    use std::os::unix::fs::PermissionsExt;
+    assert_eq!(
+        std::fs::metadata(&dir).unwrap().permissions().mode() & 0o777,
+        0o700
+    );
+
+    // Nothing is written where another user could swap the claims
+    std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o777)).unwrap();
+    assert!(newer.claim(&IOPath::I2C(5)).is_err());
+    std::fs::remove_dir_all(dir).unwrap();
+}
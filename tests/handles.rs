@@ -0,0 +1,35 @@
+use core::cell::Cell;
+use ddcbacklight::*;
+use ddcutil_sys::bindings::DDCRC_ALREADY_OPEN;
+
+/// Selecting a display opens it, selecting it again (like the daemon does before running a job)
+/// must reuse the handle
+#[test]
+fn select_twice() {
+    let handles = Handles::new();
+    let opened = Cell::new(0);
+    // Like libddcutil, refuse to open a display that is already open
+    let open = || {
+        opened.set(opened.get() + 1);
+        if opened.get() > 1 {
+            LibDDCUtilError::from_rc(DDCRC_ALREADY_OPEN)?;
+        }
+        Ok(opened.get())
+    };
+    for _ in 0..2 {
+        handles.with(IOPath::I2C(4), open, |_| Ok(())).unwrap();
+    }
+    let handle = handles.with(IOPath::I2C(4), open, |handle| Ok(*handle));
+    assert_eq!(handle.unwrap(), 1);
+    assert_eq!(opened.get(), 1);
+
+    // Another display gets its own handle
+    let opened = Cell::new(0);
+    let open = || {
+        opened.set(opened.get() + 1);
+        Ok(10)
+    };
+    let handle = handles.with(IOPath::I2C(5), open, |handle| Ok(*handle));
+    assert_eq!(handle.unwrap(), 10);
+    assert_eq!(opened.get(), 1);
+}
@@ -0,0 +1,205 @@
+#![cfg(feature = "i2c")]
+use ddcbacklight::*;
+use ddcutil_sys::bindings::{DDCRC_DDC_DATA, DDCRC_REPORTED_UNSUPPORTED, DDCRC_RETRIES};
+use std::collections::HashMap;
+
+/// A monitor answering DDC/CI requests the way a real one would on the i2c bus
+#[derive(Default)]
+struct FakeMonitor {
+    address: u16,
+    vcp: HashMap<u8, (u16, u16)>,
+    capabilities: Vec<u8>,
+    edid: Vec<u8>,
+    reply: Vec<u8>,
+    /// Corrupt the checksum of this many replies
+    corrupt: usize,
+    writes: Vec<Vec<u8>>,
+}
+
+fn checksum(init: u8, bytes: &[u8]) -> u8 {
+    bytes.iter().fold(init, |acc, b| acc ^ b)
+}
+
+impl FakeMonitor {
+    fn reply(&mut self, payload: &[u8]) {
+        let mut packet = vec![0x6e, 0x80 | payload.len() as u8];
+        packet.extend_from_slice(payload);
+        let mut chk = checksum(0x50, &packet);
+        if self.corrupt > 0 {
+            self.corrupt -= 1;
+            chk ^= 0xff;
+        }
+        packet.push(chk);
+        self.reply = packet;
+    }
+}
+
+impl I2cDevice for FakeMonitor {
+    fn set_address(&mut self, address: u16) -> std::io::Result<()> {
+        self.address = address;
+        Ok(())
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<()> {
+        let src = match self.address {
+            EDID_ADDRESS => &self.edid,
+            DDC_ADDRESS => &self.reply,
+            _ => return Err(std::io::ErrorKind::NotFound.into()),
+        };
+        buf.fill(0);
+        let len = buf.len().min(src.len());
+        buf[..len].copy_from_slice(&src[..len]);
+        Ok(())
+    }
+
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        if self.address != DDC_ADDRESS {
+            return Ok(());
+        }
+        self.writes.push(buf.to_vec());
+        assert_eq!(buf[0], 0x51);
+        assert_eq!(checksum(0x6e, &buf[..buf.len() - 1]), buf[buf.len() - 1]);
+        match &buf[2..buf.len() - 1] {
+            [0x01, code] => match self.vcp.get(code).copied() {
+                Some((current, max)) => {
+                    let [mh, ml] = max.to_be_bytes();
+                    let [sh, sl] = current.to_be_bytes();
+                    self.reply(&[0x02, 0x00, *code, 0x00, mh, ml, sh, sl])
+                }
+                None => self.reply(&[0x02, 0x01, *code, 0, 0, 0, 0, 0]),
+            },
+            [0x03, code, hi, lo] => {
+                if let Some((current, _)) = self.vcp.get_mut(code) {
+                    *current = u16::from_be_bytes([*hi, *lo]);
+                }
+            }
+            [0xf3, hi, lo] => {
+                let offset = u16::from_be_bytes([*hi, *lo]) as usize;
+                let end = (offset + 32).min(self.capabilities.len());
+                let mut payload = vec![0xe3, *hi, *lo];
+                payload.extend_from_slice(&self.capabilities[offset.min(end)..end]);
+                self.reply(&payload);
+            }
+            other => panic!("Unexpected request {:02x?}", other),
+        }
+        Ok(())
+    }
+}
+
+fn ddc(monitor: FakeMonitor) -> DDCCI<FakeMonitor> {
+    DDCCI::new(monitor).with_delays(Delays::default().scaled(0.0))
+}
+
+fn edid() -> Vec<u8> {
+    let mut edid = vec![0; 128];
+    edid[..8].copy_from_slice(&Edid::HEADER);
+    // DEL
+    edid[8..10].copy_from_slice(&[0x10, 0xac]);
+    edid[10..12].copy_from_slice(&0xa0f4u16.to_le_bytes());
+    edid[12..16].copy_from_slice(&0x12345678u32.to_le_bytes());
+    edid[17] = 30;
+    edid[54..59].copy_from_slice(&[0, 0, 0, 0xfc, 0]);
+    edid[59..72].copy_from_slice(b"DELL U2720Q\n ");
+    edid[72..77].copy_from_slice(&[0, 0, 0, 0xff, 0]);
+    edid[77..90].copy_from_slice(b"ABC123\n      ");
+    let sum = edid.iter().fold(0u8, |a, b| a.wrapping_add(*b));
+    edid[127] = sum.wrapping_neg();
+    edid
+}
+
+fn status(err: DDCError) -> Option<i32> {
+    match err.kind() {
+        DdcutilErrorKind::LibDDCUtilError(e) => Some(e.status()),
+        _ => None,
+    }
+}
+
+#[test]
+fn get_set_vcp() {
+    let mut ddc = ddc(FakeMonitor {
+        vcp: HashMap::from([(0x10, (50, 100))]),
+        ..Default::default()
+    });
+    let feature = ddc.get_vcp(0x10).unwrap();
+    assert_eq!((feature.current, feature.max), (50, 100));
+    ddc.set_vcp(0x10, 300).unwrap();
+    assert_eq!(ddc.get_vcp(0x10).unwrap().current, 300);
+    let monitor = ddc.into_inner();
+    assert_eq!(
+        monitor.writes[1],
+        [0x51, 0x84, 0x03, 0x10, 0x01, 0x2c, 0x85]
+    );
+}
+
+#[test]
+fn unsupported_vcp() {
+    let mut ddc = ddc(FakeMonitor::default());
+    let err = ddc.get_vcp(0x12).unwrap_err();
+    assert_eq!(status(err), Some(DDCRC_REPORTED_UNSUPPORTED));
+}
+
+#[test]
+fn checksum_retries() {
+    let mut ddc = ddc(FakeMonitor {
+        vcp: HashMap::from([(0x10, (50, 100))]),
+        corrupt: 2,
+        ..Default::default()
+    });
+    assert_eq!(ddc.get_vcp(0x10).unwrap().current, 50);
+
+    let mut ddc = ddc_with_corrupt(10);
+    assert_eq!(status(ddc.get_vcp(0x10).unwrap_err()), Some(DDCRC_RETRIES));
+}
+
+fn ddc_with_corrupt(corrupt: usize) -> DDCCI<FakeMonitor> {
+    ddc(FakeMonitor {
+        vcp: HashMap::from([(0x10, (50, 100))]),
+        corrupt,
+        ..Default::default()
+    })
+}
+
+#[test]
+fn capabilities_fragments() {
+    let caps = "(prot(monitor)type(lcd)model(U2720Q)cmds(01 02 03 07 0C E3 F3)vcp(02 04 05 10 12 60(0F 11 12)))";
+    let mut ddc = ddc(FakeMonitor {
+        capabilities: caps.as_bytes().to_vec(),
+        ..Default::default()
+    });
+    assert_eq!(ddc.capabilities().unwrap(), caps);
+    // 3 full fragments, 1 partial and the empty terminator
+    assert_eq!(ddc.into_inner().writes.len(), caps.len().div_ceil(32) + 1);
+}
+
+#[test]
+fn capabilities_too_long() {
+    let mut ddc = ddc(FakeMonitor {
+        capabilities: vec![b'a'; 5000],
+        ..Default::default()
+    });
+    assert_eq!(
+        status(ddc.capabilities().unwrap_err()),
+        Some(DDCRC_DDC_DATA)
+    );
+    // Gives up after 64 fragments and one more
+    assert_eq!(ddc.into_inner().writes.len(), 65);
+}
+
+#[test]
+fn edid_parse() {
+    let mut ddc = ddc(FakeMonitor {
+        edid: edid(),
+        ..Default::default()
+    });
+    let edid = Edid::parse(&ddc.edid().unwrap()).unwrap();
+    assert_eq!(edid.manufacturer, "DEL");
+    assert_eq!(edid.product_code, 0xa0f4);
+    assert_eq!(edid.serial_number, 0x12345678);
+    assert_eq!(edid.year, 2020);
+    assert_eq!(edid.model.as_deref(), Some("DELL U2720Q"));
+    assert_eq!(edid.serial.as_deref(), Some("ABC123"));
+
+    let mut corrupt = self::edid();
+    corrupt[20] ^= 1;
+    assert_eq!(Edid::parse(&corrupt), None);
+}
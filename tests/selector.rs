@@ -0,0 +1,101 @@
+use ddcbacklight::*;
+
+fn driver() -> MockDDC {
+    let identity = |mfg: &'static str, product, serial: &'static str| {
+        move |identity: &mut DisplayIdentity| {
+            identity.manufacturer = mfg.into();
+            identity.product_code = product;
+            identity.serial = serial.into();
+        }
+    };
+    MockDDC::new()
+        .with_display(
+            MockDisplay::new("DELL U2720Q", "card1-DP-1", 4)
+                .with_identity(identity("DEL", 0xa0f2, "5KC2R83")),
+        )
+        .with_display(
+            MockDisplay::new("DELL U2720Q", "card1-DP-2", 5)
+                .with_identity(identity("DEL", 0xa0f2, "7XQ1T02")),
+        )
+        .with_display(
+            MockDisplay::new("LG HDR 4K", "card1-HDMI-A-1", 6)
+                .with_identity(identity("GSM", 0x5b77, "")),
+        )
+}
+
+/// The i2c buses of the displays `selector` selects
+fn select(driver: &MockDDC, selector: &str) -> Vec<IOPath> {
+    let selector: DisplaySelector = selector.parse().unwrap();
+    driver
+        .select(&selector)
+        .unwrap()
+        .into_iter()
+        .map(|(display, _)| display.io_path)
+        .collect()
+}
+
+#[test]
+fn parse() {
+    use DisplaySelector::*;
+    assert_eq!(
+        "model=LG HDR 4K".parse::<DisplaySelector>().unwrap(),
+        Model("LG HDR 4K".into())
+    );
+    assert_eq!(
+        "product=0xa0f2".parse::<DisplaySelector>().unwrap(),
+        ProductCode(0xa0f2)
+    );
+    assert_eq!(
+        "display=1|hiddev=2".parse::<DisplaySelector>().unwrap(),
+        Or(vec![Display(1), Hiddev(2)])
+    );
+    assert_eq!(
+        "!connector=HDMI,!!sn=X".parse::<DisplaySelector>().unwrap(),
+        And(vec![
+            Not(Box::new(Connector("HDMI".into()))),
+            Not(Box::new(Not(Box::new(Serial("X".into()))))),
+        ])
+    );
+    assert!("bus".parse::<DisplaySelector>().is_err());
+    assert!("colour=red".parse::<DisplaySelector>().is_err());
+    assert!("bus=four".parse::<DisplaySelector>().is_err());
+    assert!("product=0x10000".parse::<DisplaySelector>().is_err());
+    assert!("edid=00ff".parse::<DisplaySelector>().is_err());
+}
+
+#[test]
+fn matches() {
+    let driver = driver();
+    assert_eq!(select(&driver, "model=dell u2720q").len(), 2);
+    assert_eq!(select(&driver, "serial=7XQ1T02"), [IOPath::I2C(5)]);
+    assert_eq!(select(&driver, "mfg=del,!serial=5KC2R83"), [IOPath::I2C(5)]);
+    assert_eq!(
+        select(&driver, "product=0x5b77|bus=4"),
+        [IOPath::I2C(4), IOPath::I2C(6)]
+    );
+    assert_eq!(select(&driver, "display=1"), [IOPath::I2C(5)]);
+    assert_eq!(select(&driver, "connector=hdmi"), [IOPath::I2C(6)]);
+    assert!(select(&driver, "hiddev=4").is_empty());
+    assert_eq!(driver.select(&DisplaySelector::default()).unwrap().len(), 3);
+    assert!(driver
+        .select(&DisplaySelector::Or(Vec::new()))
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn model_serial() {
+    let selector = |s: &str| s.parse::<DisplaySelector>().unwrap();
+    assert_eq!(
+        selector("serial=5KC2R83").model_serial(),
+        Some((None, None, "5KC2R83"))
+    );
+    assert_eq!(
+        selector("mfg=DEL,model=DELL U2720Q,serial=5KC2R83").model_serial(),
+        Some((Some("DEL"), Some("DELL U2720Q"), "5KC2R83"))
+    );
+    // Several monitors could match those
+    assert_eq!(selector("model=DELL U2720Q").model_serial(), None);
+    assert_eq!(selector("serial=5KC2R83|bus=4").model_serial(), None);
+    assert_eq!(selector("serial=5KC2R83,bus=4").model_serial(), None);
+}
@@ -0,0 +1,125 @@
+use ddcbacklight::*;
+
+#[test]
+fn percent_round_trip() {
+    for max in 1..=1000 {
+        let backlight = |current| Backlight { current, max };
+        // Every raw value maps to a percent that maps back to a raw value with the same percent
+        for raw in 0..=max {
+            let percent = backlight(raw).percent();
+            assert!(percent <= 100);
+            let back = backlight(0).from_percent(percent);
+            assert_eq!(
+                backlight(back).percent(),
+                percent,
+                "max {} raw {}",
+                max,
+                raw
+            );
+        }
+        // With at least 100 raw values every percent is reachable
+        if max >= 100 {
+            for percent in 0..=100 {
+                let raw = backlight(0).raw(BrightnessUnit::Percent, percent).unwrap();
+                assert_eq!(backlight(raw).percent(), percent, "max {}", max);
+            }
+        }
+    }
+}
+
+#[test]
+fn out_of_range() {
+    let backlight = Backlight {
+        current: 10,
+        max: 50,
+    };
+    assert_eq!(backlight.raw(BrightnessUnit::Percent, 100).unwrap(), 50);
+    assert!(backlight.raw(BrightnessUnit::Percent, 101).is_err());
+    assert_eq!(backlight.raw(BrightnessUnit::Raw, 50).unwrap(), 50);
+    assert!(backlight.raw(BrightnessUnit::Raw, 51).is_err());
+}
+
+#[test]
+fn step() {
+    let backlight = Backlight {
+        current: 10,
+        max: 50,
+    };
+    assert_eq!(backlight.step(BrightnessUnit::Percent, 10), 15);
+    assert_eq!(backlight.step(BrightnessUnit::Percent, 1), 11);
+    assert_eq!(backlight.step(BrightnessUnit::Percent, -1), 9);
+    assert_eq!(backlight.step(BrightnessUnit::Percent, -100), 0);
+    assert_eq!(backlight.step(BrightnessUnit::Percent, 0), 10);
+    assert_eq!(backlight.step(BrightnessUnit::Raw, 100), 50);
+    assert_eq!(backlight.step(BrightnessUnit::Raw, -3), 7);
+    let full = Backlight {
+        current: 50,
+        max: 50,
+    };
+    assert_eq!(full.step(BrightnessUnit::Percent, 1), 50);
+}
+
+#[test]
+fn brightness_change() {
+    let amount = |value, unit| BrightnessAmount { value, unit };
+    let percent = BrightnessUnit::Percent;
+    let raw = BrightnessUnit::Raw;
+    for (expression, change) in [
+        ("50%", BrightnessChange::Set(amount(50, percent))),
+        ("30", BrightnessChange::Set(amount(30, raw))),
+        ("+10%", BrightnessChange::Increase(amount(10, percent))),
+        ("+5", BrightnessChange::Increase(amount(5, raw))),
+        ("-5", BrightnessChange::Decrease(amount(5, raw))),
+        ("-10%", BrightnessChange::Decrease(amount(10, percent))),
+        ("max", BrightnessChange::Max),
+        ("min", BrightnessChange::Min),
+    ] {
+        assert_eq!(expression.parse::<BrightnessChange>().unwrap(), change);
+        assert_eq!(change.to_string(), expression);
+    }
+    assert_eq!(
+        "MAX".parse::<BrightnessChange>().unwrap(),
+        BrightnessChange::Max
+    );
+    assert_eq!(
+        " 20 % ".parse::<BrightnessChange>().unwrap(),
+        BrightnessChange::Set(amount(20, percent))
+    );
+    for invalid in ["", "%", "+", "ten", "10%%", "++1", "70000", "1.5"] {
+        assert!(invalid.parse::<BrightnessChange>().is_err(), "{}", invalid);
+    }
+
+    let backlight = Backlight {
+        current: 100,
+        max: 255,
+    };
+    let apply = |expression: &str| {
+        expression
+            .parse::<BrightnessChange>()
+            .unwrap()
+            .apply(&backlight)
+            .ok()
+    };
+    assert_eq!(apply("50%"), Some(128));
+    assert_eq!(apply("50"), Some(50));
+    assert_eq!(apply("+10"), Some(110));
+    assert_eq!(apply("-10%"), Some(74));
+    assert_eq!(apply("max"), Some(255));
+    assert_eq!(apply("min"), Some(0));
+    assert_eq!(apply("101%"), None);
+    assert_eq!(apply("300"), None);
+}
+
+#[test]
+fn power_mode() {
+    let read =
+        |code, value| PowerMode::try_from(&VCPFeature::from_bytes(code, 0, 5, 0, value)).ok();
+    assert_eq!(read(VCPFeature::POWER_MODE, 1), Some(PowerMode::On));
+    assert_eq!(read(VCPFeature::POWER_MODE, 4), Some(PowerMode::Off));
+    assert_eq!(read(VCPFeature::POWER_MODE, 5), Some(PowerMode::PowerOff));
+    assert_eq!(read(VCPFeature::POWER_MODE, 9), None);
+    assert_eq!(read(VCPFeature::POWER_CONTROL, 0), Some(PowerMode::Off));
+    assert_eq!(read(VCPFeature::POWER_CONTROL, 1), Some(PowerMode::On));
+    assert_eq!(PowerMode::Suspend.code(), 0x03);
+    assert_eq!(PowerMode::Standby.to_string(), "standby");
+}